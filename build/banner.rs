@@ -0,0 +1,249 @@
+//! The ROM banner, the icon and titles the firmware's menu and emulators show for the game.
+//!
+//! Laid out as GBATEK describes it, all integers little endian:
+//!
+//! | Offset  | Size      | Contents                                                 |
+//! |---------|-----------|----------------------------------------------------------|
+//! | 0x0000  | 2         | version, 1 to 3 for 6 to 8 languages, 0x103 for DSi ones |
+//! | 0x0002  | 8         | CRC-16 of each version's part, 0 when absent             |
+//! | 0x0020  | 0x200     | the icon, 4x4 tiles of 4bpp                              |
+//! | 0x0220  | 0x20      | its 16 color palette, color 0 being transparent          |
+//! | 0x0240  | 0x100 × 8 | titles in UTF-16, in [`LANGUAGES`] order                 |
+//! | 0x1240  | 0x200 × 8 | DSi icon frames                                          |
+//! | 0x2240  | 0x20 × 8  | DSi icon palettes                                        |
+//! | 0x2340  | 2 × 64    | DSi icon sequence                                        |
+//!
+//! The banner is as long as its version needs: 0x840, 0x940, 0x1240 or 0x23C0 bytes.
+
+use serde::Deserialize;
+
+use crate::{
+    gfx::{Format, Indexed},
+    image::COLOR_KEY,
+    palette::{BankError, Depth, Palette},
+    pxo::Project,
+};
+
+/// Languages of the titles, from the oldest banners' 6 to the 8 of version 3.
+pub const LANGUAGES: [&str; 8] = [
+    "japanese", "english", "french", "german", "italian", "spanish", "chinese", "korean",
+];
+const ICON_SIZE: u32 = 32;
+const TITLE_LEN: usize = 0x100;
+/// Of the DSi icon, whose frames can be distinct bitmaps and palettes.
+const MAX_BITMAPS: usize = 8;
+const MAX_PALETTES: usize = 8;
+/// The sequence ends with a 0 token, which loops it.
+const MAX_TOKENS: usize = 63;
+
+/// Text of a title, up to three lines on the menu.
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Title {
+    pub title: String,
+    pub subtitle: Option<String>,
+    pub author: Option<String>,
+}
+
+/// The `[banner]` table of the manifest: the title in English, used by the languages without
+/// their own table.
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BannerOptions {
+    pub title: String,
+    pub subtitle: Option<String>,
+    pub author: Option<String>,
+    pub japanese: Option<Title>,
+    pub french: Option<Title>,
+    pub german: Option<Title>,
+    pub italian: Option<Title>,
+    pub spanish: Option<Title>,
+    pub chinese: Option<Title>,
+    pub korean: Option<Title>,
+}
+
+impl BannerOptions {
+    /// The title in each of [`LANGUAGES`].
+    fn titles(&self) -> [Title; 8] {
+        let english = Title {
+            title: self.title.clone(),
+            subtitle: self.subtitle.clone(),
+            author: self.author.clone(),
+        };
+        let or_english = |title: &Option<Title>| title.clone().unwrap_or_else(|| english.clone());
+        [
+            or_english(&self.japanese),
+            english.clone(),
+            or_english(&self.french),
+            or_english(&self.german),
+            or_english(&self.italian),
+            or_english(&self.spanish),
+            or_english(&self.chinese),
+            or_english(&self.korean),
+        ]
+    }
+}
+
+/// CRC-16 as the BIOS computes it, which is what the firmware checks banners with.
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0xFFFF, |mut crc, &b| {
+        crc ^= b as u16;
+        for _ in 0..8 {
+            crc = crc >> 1 ^ 0xA001 & (crc & 1).wrapping_neg();
+        }
+        crc
+    })
+}
+
+/// Lines of `title` joined by newlines in UTF-16, which has to leave room for a terminator.
+fn encode_title(title: &Title, language: &str) -> Result<Vec<u8>, String> {
+    let lines: Vec<&str> = [
+        Some(&title.title),
+        title.subtitle.as_ref(),
+        title.author.as_ref(),
+    ]
+    .into_iter()
+    .flatten()
+    .map(String::as_str)
+    .collect();
+    let units: Vec<u16> = lines.join("\n").encode_utf16().collect();
+    if units.len() >= TITLE_LEN / 2 {
+        return Err(format!(
+            "the {language} title is {} UTF-16 units long, it can be up to {}",
+            units.len(),
+            TITLE_LEN / 2 - 1
+        ));
+    }
+    let mut out: Vec<u8> = units.iter().flat_map(|u| u.to_le_bytes()).collect();
+    out.resize(TITLE_LEN, 0);
+    Ok(out)
+}
+
+/// Builds the banner from `icon` and `options`. An icon with more than one frame becomes an
+/// animated DSi icon, its first frame being the icon of older consoles.
+pub fn build(icon: &Project, options: &BannerOptions) -> Result<Vec<u8>, String> {
+    if (icon.width, icon.height) != (ICON_SIZE, ICON_SIZE) {
+        return Err(format!(
+            "the icon is {}x{}, it has to be {ICON_SIZE}x{ICON_SIZE}",
+            icon.width, icon.height
+        ));
+    }
+    let sets: Vec<_> = icon
+        .frames
+        .iter()
+        .map(|f| crate::palette::color_set([&f.image]))
+        .collect();
+    // Each palette bank is a palette of the DSi icon.
+    let (palette, banks) = Palette::pack_banks(COLOR_KEY, &sets).map_err(|e| match e {
+        BankError::TooManyColors { asset, colors } => format!(
+            "frame {} of the icon uses {colors} colors, it can use {}",
+            asset + 1,
+            Depth::Bpp4.max_colors()
+        ),
+        BankError::OutOfBanks { .. } => "the icon needs too many palettes".into(),
+    })?;
+    let palettes = palette.to_bytes();
+    let palette_bytes = |bank: u8| {
+        let start = bank as usize * Palette::BANK_COLORS * 2;
+        palettes[start..start + Palette::BANK_COLORS * 2].to_vec()
+    };
+    if banks.iter().any(|&bank| bank as usize >= MAX_PALETTES) {
+        return Err(format!(
+            "the icon's frames need more than {MAX_PALETTES} palettes"
+        ));
+    }
+
+    let mut bitmaps: Vec<Vec<u8>> = vec![];
+    let mut sequence = vec![];
+    for (frame, &bank) in icon.frames.iter().zip(&banks) {
+        let bitmap =
+            Indexed::new(&frame.image, &palette, Some(bank)).encode(Format::Tiled, Depth::Bpp4)?;
+        // Identical frames share a bitmap, the limit is on distinct ones.
+        let index = match bitmaps.iter().position(|b| *b == bitmap) {
+            Some(index) => index,
+            None => {
+                bitmaps.push(bitmap);
+                bitmaps.len() - 1
+            }
+        };
+        // Durations are in 60Hz frames, longer ones than a token holds take several.
+        let mut ticks = ((frame.duration_ms as u32 * 60 + 500) / 1000).max(1);
+        while ticks > 0 {
+            let token_ticks = ticks.min(0xFF);
+            sequence.push(token_ticks as u16 | (index as u16) << 8 | (bank as u16) << 11);
+            ticks -= token_ticks;
+        }
+    }
+    if bitmaps.len() > MAX_BITMAPS {
+        return Err(format!(
+            "the icon has {} distinct frames, an animated icon can have {MAX_BITMAPS}",
+            bitmaps.len()
+        ));
+    }
+    if sequence.len() > MAX_TOKENS {
+        return Err(format!(
+            "the icon's animation takes {} steps, it can take {MAX_TOKENS}",
+            sequence.len()
+        ));
+    }
+
+    let animated = icon.frames.len() > 1;
+    let version: u16 = if animated {
+        0x103
+    } else if options.korean.is_some() {
+        3
+    } else if options.chinese.is_some() {
+        2
+    } else {
+        1
+    };
+    let languages = match version {
+        1 => 6,
+        2 => 7,
+        _ => 8,
+    };
+    let mut out = vec![0; 0x20];
+    out.extend(&bitmaps[0]);
+    out.extend(palette_bytes(banks[0]));
+    for (title, language) in options.titles().iter().zip(LANGUAGES).take(languages) {
+        out.extend(encode_title(title, language)?);
+    }
+    if animated {
+        // Reserved space after the titles.
+        out.resize(0x1240, 0);
+        for i in 0..MAX_BITMAPS {
+            match bitmaps.get(i) {
+                Some(bitmap) => out.extend(bitmap),
+                None => out.extend([0; 0x200]),
+            }
+        }
+        for bank in 0..MAX_PALETTES as u8 {
+            out.extend(palette_bytes(bank));
+        }
+        for token in sequence
+            .iter()
+            .copied()
+            .chain(std::iter::repeat(0))
+            .take(64)
+        {
+            out.extend(token.to_le_bytes());
+        }
+    } else if version == 3 {
+        out.resize(0x1240, 0);
+    }
+
+    out[..2].copy_from_slice(&version.to_le_bytes());
+    let crcs = [
+        (2, 0x20..0x840),
+        (4, 0x20..0x940),
+        (6, 0x20..0xA40),
+        (8, 0x1240..0x23C0),
+    ];
+    for (at, range) in crcs {
+        if range.end <= out.len() {
+            let crc = crc16(&out[range]);
+            out[at..at + 2].copy_from_slice(&crc.to_le_bytes());
+        }
+    }
+    Ok(out)
+}
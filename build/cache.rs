@@ -0,0 +1,216 @@
+//! The conversion cache, so a build only converts the assets that changed since the last one.
+//!
+//! A conversion is looked up by a [`Key`] hashing everything it depends on that is known up
+//! front: the build script itself, the source files' paths and contents, their options in the
+//! manifest and how they are converted. Files a conversion finds it needs along the way, like a
+//! level's tilesets, are [`track`]ed, stored in the entry with a hash of their contents, and
+//! checked again before the entry is reused.
+//!
+//! Entries live in `OUT_DIR`, one file each: the length of a JSON header (u32, little endian),
+//! the header with the tracked files, the conversion's info and the names and lengths of the
+//! files it produced, then those files one after the other. Entries unused by a build are
+//! removed at the end of it.
+
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::{AssetError, Result};
+
+/// Bumped when the entry layout changes. Changes to conversions don't need it, they change the
+/// build script and with it every key.
+const VERSION: u32 = 1;
+const EXTENSION: &str = "entry";
+/// Set to have the build warn how many conversions were cached.
+const STATS_VAR: &str = "DHGAME_CACHE_STATS";
+
+thread_local! {
+    /// Files tracked by the conversion in progress, if any.
+    static TRACKED: RefCell<Option<Vec<PathBuf>>> = const { RefCell::new(None) };
+}
+
+/// Tells Cargo to run the build script again when `path` changes, and makes it a dependency of
+/// the conversion in progress.
+pub fn track(path: &Path) {
+    println!("cargo:rerun-if-changed={}", path.display());
+    TRACKED.with_borrow_mut(|tracked| {
+        if let Some(tracked) = tracked {
+            tracked.push(path.to_owned());
+        }
+    });
+}
+
+/// Hash of a file's contents, `None` if it can't be read.
+fn content_hash(path: &Path) -> Option<u64> {
+    let bytes = std::fs::read(path).ok()?;
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+/// What a conversion produced: files for the asset pack, and what the generated code needs.
+pub struct Output<T> {
+    pub info: T,
+    pub files: Vec<(String, Vec<u8>)>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Header<T> {
+    tracked: Vec<(PathBuf, Option<u64>)>,
+    info: T,
+    files: Vec<(String, usize)>,
+}
+
+/// Everything a conversion depends on that is known before running it.
+pub struct Key(DefaultHasher);
+
+impl Key {
+    /// Adds the path and contents of `path`.
+    pub fn file(mut self, path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path)
+            .map_err(|e| AssetError::new(path, format!("could not open: {e}")))?;
+        path.hash(&mut self.0);
+        bytes.hash(&mut self.0);
+        Ok(self)
+    }
+
+    pub fn value(mut self, value: impl Hash) -> Self {
+        value.hash(&mut self.0);
+        self
+    }
+}
+
+pub struct Cache {
+    dir: PathBuf,
+    /// Hash of the build script's executable.
+    build_script: u64,
+    used: HashSet<String>,
+    hits: usize,
+    misses: usize,
+}
+
+impl Cache {
+    pub fn open(dir: PathBuf) -> Result<Self> {
+        std::fs::create_dir_all(&dir).map_err(|e| AssetError::new(&dir, e.to_string()))?;
+        let exe = std::env::current_exe().map_err(|e| AssetError::new(&dir, e.to_string()))?;
+        let build_script = content_hash(&exe)
+            .ok_or_else(|| AssetError::new(&exe, "could not read the build script"))?;
+        Ok(Self {
+            dir,
+            build_script,
+            used: HashSet::new(),
+            hits: 0,
+            misses: 0,
+        })
+    }
+
+    pub fn key(&self) -> Key {
+        let mut hasher = DefaultHasher::new();
+        (VERSION, self.build_script).hash(&mut hasher);
+        Key(hasher)
+    }
+
+    /// The output of the conversion with `key`, running `convert` if it isn't cached or a file
+    /// it tracked has changed.
+    pub fn convert<T: Serialize + DeserializeOwned>(
+        &mut self,
+        key: Key,
+        convert: impl FnOnce() -> Result<Output<T>>,
+    ) -> Result<Output<T>> {
+        let name = format!("{:016x}.{EXTENSION}", key.0.finish());
+        let path = self.dir.join(&name);
+        self.used.insert(name);
+        if let Some(output) = read_entry(&path) {
+            self.hits += 1;
+            return Ok(output);
+        }
+
+        self.misses += 1;
+        TRACKED.set(Some(vec![]));
+        let output = convert();
+        let tracked = TRACKED.take().unwrap_or_default();
+        let output = output?;
+        let header = Header {
+            tracked: tracked
+                .into_iter()
+                .map(|path| {
+                    let hash = content_hash(&path);
+                    (path, hash)
+                })
+                .collect(),
+            info: &output.info,
+            files: (output.files.iter())
+                .map(|(name, data)| (name.clone(), data.len()))
+                .collect(),
+        };
+        let header = serde_json::to_vec(&header).unwrap();
+        let mut entry = Vec::with_capacity(4 + header.len());
+        entry.extend((header.len() as u32).to_le_bytes());
+        entry.extend(header);
+        for (_, data) in &output.files {
+            entry.extend(data);
+        }
+        // Written aside then renamed, so an interrupted build can't leave half an entry.
+        let partial = path.with_extension("partial");
+        std::fs::write(&partial, entry)
+            .and_then(|_| std::fs::rename(&partial, &path))
+            .map_err(|e| AssetError::new(&path, e.to_string()))?;
+        Ok(output)
+    }
+
+    /// Removes the entries this build didn't use, and tells how many conversions were cached if
+    /// `DHGAME_CACHE_STATS` is set.
+    pub fn finish(self) -> Result<()> {
+        let err = |e: std::io::Error| AssetError::new(&self.dir, e.to_string());
+        for entry in self.dir.read_dir().map_err(err)? {
+            let entry = entry.map_err(err)?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !self.used.contains(&name) {
+                std::fs::remove_file(entry.path()).map_err(err)?;
+            }
+        }
+        // Cargo shows warnings on every build, so only when asked for.
+        println!("cargo:rerun-if-env-changed={STATS_VAR}");
+        if std::env::var_os(STATS_VAR).is_some() {
+            println!(
+                "cargo:warning={} conversions redone, {} cached",
+                self.misses, self.hits
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Reads the entry at `path`, `None` if there is none, it is damaged, or a file it tracked has
+/// changed.
+fn read_entry<T: DeserializeOwned>(path: &Path) -> Option<Output<T>> {
+    let entry = std::fs::read(path).ok()?;
+    let header_len = u32::from_le_bytes(entry.get(..4)?.try_into().unwrap()) as usize;
+    let header: Header<T> = serde_json::from_slice(entry.get(4..4 + header_len)?).ok()?;
+    if header
+        .tracked
+        .iter()
+        .any(|(path, hash)| content_hash(path) != *hash)
+    {
+        return None;
+    }
+    // Tracked again for Cargo, which only remembers what the last run printed.
+    for (path, _) in &header.tracked {
+        track(path);
+    }
+    let mut at = 4 + header_len;
+    let mut files = Vec::with_capacity(header.files.len());
+    for (name, len) in header.files {
+        files.push((name, entry.get(at..at + len)?.to_vec()));
+        at += len;
+    }
+    Some(Output {
+        info: header.info,
+        files,
+    })
+}
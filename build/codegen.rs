@@ -0,0 +1,609 @@
+use std::{fmt::Write, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    collision::{Hitbox, Rect},
+    gfx::{Format, MapType},
+    palette::Depth,
+    prefab::Prefab,
+    pxo::Tag,
+    sound::SoundFormat,
+};
+
+/// What the game needs to know about a converted image.
+#[derive(Serialize, Deserialize)]
+pub struct AssetInfo {
+    pub stem: String,
+    /// Path in the asset pack.
+    pub pack_path: String,
+    pub width: u32,
+    pub height: u32,
+    pub kind: AssetKind,
+    pub depth: Depth,
+    /// Palette bank of 4bpp assets.
+    pub palette_bank: u8,
+    /// Duration of each frame in milliseconds, 0 for still images.
+    pub frame_durations: Vec<u16>,
+    pub tags: Vec<Tag>,
+    /// Map of tiled backgrounds, whose `pack_path` holds the tiles.
+    pub map: Option<MapInfo>,
+    /// Collision data of sprites.
+    pub collision: Option<CollisionInfo>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MapInfo {
+    pub pack_path: String,
+    pub tile_count: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CollisionInfo {
+    /// Path of the masks of every frame in the asset pack.
+    pub mask_path: String,
+    /// Bounds of each frame's opaque pixels.
+    pub bounds: Vec<Rect>,
+    pub hitboxes: Vec<Hitbox>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum AssetKind {
+    /// Name of the `SpriteSize` variant.
+    Sprite { size: String },
+    /// Names of the `bg::Type` variant and its size enum variant, e.g. `Bitmap8Size::B8_256x256`.
+    Background { type_: String, size: String },
+}
+
+/// An asset group converted together, sharing one palette.
+#[derive(Serialize, Deserialize)]
+pub struct GroupInfo {
+    /// Module the group's constants are placed in, `None` for the top level.
+    pub module: Option<String>,
+    /// `None` for direct color groups.
+    pub palette_path: Option<String>,
+    pub palette_colors: usize,
+    pub assets: Vec<AssetInfo>,
+}
+
+/// A level converted to `.lvl`.
+#[derive(Serialize, Deserialize)]
+pub struct LevelInfo {
+    pub name: String,
+    pub pack_path: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// A font converted to `.fnt`.
+#[derive(Serialize, Deserialize)]
+pub struct FontInfo {
+    pub name: String,
+    pub pack_path: String,
+    pub line_height: u32,
+    pub glyphs: usize,
+}
+
+/// A sound converted into the sound bank.
+#[derive(Serialize, Deserialize)]
+pub struct SoundInfo {
+    pub name: String,
+    /// Path relative to `data/`.
+    pub source: String,
+    pub format: SoundFormat,
+    pub rate: u32,
+    pub duration_secs: f32,
+    /// Word of its data the sound loops back to, `None` if it plays once.
+    pub loop_word: Option<u32>,
+}
+
+/// Turns a file stem like `Squid` or `big-rock` into `SQUID` or `BIG_ROCK`.
+pub fn const_name(stem: &str) -> String {
+    let mut name = String::new();
+    let mut prev_lower = false;
+    for c in stem.chars() {
+        if c.is_ascii_uppercase() && prev_lower {
+            name.push('_');
+        }
+        prev_lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+        if c.is_ascii_alphanumeric() {
+            name.push(c.to_ascii_uppercase());
+        } else {
+            name.push('_');
+        }
+    }
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
+    name
+}
+
+/// The first two of `sources` whose stems have the same [`const_name`], like `big-rock.png` and
+/// `big_rock.png`, whose statics would clash in the generated module.
+pub fn const_name_clash<P: AsRef<Path>>(sources: &[P]) -> Option<(&Path, &Path)> {
+    let name = |path: &Path| const_name(&path.file_stem().unwrap().to_string_lossy());
+    sources.iter().enumerate().find_map(|(i, second)| {
+        let second = second.as_ref();
+        sources[..i]
+            .iter()
+            .map(AsRef::as_ref)
+            .find(|first| name(first) == name(second))
+            .map(|first| (first, second))
+    })
+}
+
+/// Turns a file stem like `jump` or `big-rock` into `Jump` or `BigRock`.
+pub fn type_name(stem: &str) -> String {
+    let mut name = String::new();
+    for word in stem.split(|c: char| !c.is_ascii_alphanumeric()) {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            name.push(first.to_ascii_uppercase());
+            name.extend(chars);
+        }
+    }
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        name.insert(0, '_');
+    }
+    name
+}
+
+/// `writeln!` into a `String`, which can't fail.
+macro_rules! emit {
+    ($out:expr) => {
+        $out.push('\n')
+    };
+    ($out:expr, $($arg:tt)*) => {
+        writeln!($out, $($arg)*).unwrap()
+    };
+}
+
+/// Generates the `assets` module included by the game, whose assets are in the pack at `pack`.
+pub fn generate(
+    pack: &str,
+    groups: &[GroupInfo],
+    levels: &[LevelInfo],
+    fonts: &[FontInfo],
+    sound_bank: &str,
+    sounds: &[SoundInfo],
+    prefabs: &[Prefab],
+) -> String {
+    let mut out = String::from("// @generated by build/main.rs, do not edit.\n");
+    emit!(out);
+    emit!(
+        out,
+        "/// The asset pack, to mount with `resources::mount` before loading anything."
+    );
+    emit!(out, "pub const PACK: &str = {pack:?};");
+    for group in groups {
+        let body = group_body(group);
+        out.push('\n');
+        let Some(module) = &group.module else {
+            out.push_str(&body);
+            continue;
+        };
+        emit!(out, "pub mod {module} {{");
+        for line in body.lines() {
+            if line.is_empty() {
+                out.push('\n');
+            } else {
+                emit!(out, "    {line}");
+            }
+        }
+        emit!(out, "}}");
+    }
+    emit!(out);
+    emit!(out, "pub mod levels {{");
+    emit!(out, "    #[allow(unused_imports)]");
+    emit!(out, "    use crate::level::LevelAsset;");
+    emit!(out, "    #[allow(unused_imports)]");
+    emit!(out, "    use libnds::resources::AssetId;");
+    for level in levels {
+        emit!(out);
+        emit!(
+            out,
+            "    /// `{}`, {}x{} pixels.",
+            level.name,
+            level.width,
+            level.height
+        );
+        emit!(
+            out,
+            "    pub static {}: LevelAsset = LevelAsset {{ id: AssetId::new({:?}) }};",
+            const_name(&level.name),
+            level.pack_path
+        );
+    }
+    emit!(out, "}}");
+    emit!(out);
+    emit!(out, "pub mod fonts {{");
+    emit!(out, "    #[allow(unused_imports)]");
+    emit!(
+        out,
+        "    use libnds::{{resources::AssetId, text::FontAsset}};"
+    );
+    for font in fonts {
+        emit!(out);
+        emit!(
+            out,
+            "    /// `{}`, {} glyphs, {} pixel lines.",
+            font.name,
+            font.glyphs,
+            font.line_height
+        );
+        emit!(
+            out,
+            "    pub static {}: FontAsset = FontAsset {{ id: AssetId::new({:?}) }};",
+            const_name(&font.name),
+            font.pack_path
+        );
+    }
+    emit!(out, "}}");
+    emit!(out);
+    emit!(out, "pub mod sounds {{");
+    emit!(
+        out,
+        "    use libnds::{{resources::AssetId, sound::SoundBankAsset}};"
+    );
+    emit!(out);
+    emit!(
+        out,
+        "    pub static BANK: SoundBankAsset = SoundBankAsset {{ id: AssetId::new({sound_bank:?}) }};"
+    );
+    emit!(out);
+    emit!(
+        out,
+        "    /// Every sound in [`BANK`], to play with `SoundBank::play`."
+    );
+    emit!(out, "    #[derive(Debug, Clone, Copy, PartialEq, Eq)]");
+    emit!(out, "    #[repr(u16)]");
+    emit!(out, "    pub enum Sound {{");
+    for (i, sound) in sounds.iter().enumerate() {
+        emit!(
+            out,
+            "        /// `{}`, {:.2}s of {} at {}Hz{}.",
+            sound.source,
+            sound.duration_secs,
+            sound.format.name(),
+            sound.rate,
+            if sound.loop_word.is_some() {
+                ", looped"
+            } else {
+                ""
+            }
+        );
+        emit!(out, "        {} = {i},", type_name(&sound.name));
+    }
+    emit!(out, "    }}");
+    emit!(out);
+    emit!(out, "    impl From<Sound> for u16 {{");
+    emit!(out, "        fn from(sound: Sound) -> u16 {{");
+    if sounds.is_empty() {
+        emit!(out, "            match sound {{}}");
+    } else {
+        emit!(out, "            sound as u16");
+    }
+    emit!(out, "        }}");
+    emit!(out, "    }}");
+    emit!(out, "}}");
+    emit!(out);
+    prefabs_module(&mut out, prefabs);
+    out
+}
+
+fn prefabs_module(out: &mut String, prefabs: &[Prefab]) {
+    emit!(out, "pub mod prefabs {{");
+    emit!(out, "    #[allow(unused_imports)]");
+    emit!(
+        out,
+        "    use crate::{{prefab::{{Behaviour, Physics, Prefab}}, vec2}};"
+    );
+    for prefab in prefabs {
+        let physics = &prefab.physics;
+        emit!(out);
+        emit!(
+            out,
+            "    /// `prefabs/{}.toml`, a `{}` with the `{}` behaviour.",
+            prefab.name,
+            prefab.sprite,
+            prefab.behaviour
+        );
+        emit!(
+            out,
+            "    pub static {}: Prefab = Prefab {{",
+            const_name(&prefab.name)
+        );
+        emit!(out, "        name: {:?},", prefab.name);
+        emit!(
+            out,
+            "        sprite: &super::{},",
+            const_name(&prefab.sprite)
+        );
+        emit!(
+            out,
+            "        behaviour: Behaviour::{},",
+            type_name(&prefab.behaviour)
+        );
+        emit!(out, "        physics: Physics {{");
+        let [x, y] = physics.velocity;
+        emit!(out, "            velocity: vec2::new({x:?}, {y:?}),");
+        emit!(out, "            gravity: {:?},", physics.gravity);
+        emit!(out, "            acceleration: {:?},", physics.acceleration);
+        emit!(out, "            drag: {:?},", physics.drag);
+        match physics.max_speed {
+            Some(max_speed) => emit!(out, "            max_speed: {max_speed:?},"),
+            None => emit!(out, "            max_speed: f32::INFINITY,"),
+        }
+        emit!(out, "            jump_speed: {:?},", physics.jump_speed);
+        emit!(out, "        }},");
+        emit!(out, "    }};");
+    }
+    emit!(out);
+    emit!(
+        out,
+        "    /// Every prefab, to look them up by name with `prefab::find`."
+    );
+    let all: Vec<_> = prefabs
+        .iter()
+        .map(|p| format!("&{}", const_name(&p.name)))
+        .collect();
+    emit!(
+        out,
+        "    pub static ALL: [&Prefab; {}] = [{}];",
+        prefabs.len(),
+        all.join(", ")
+    );
+    emit!(out, "}}");
+}
+
+fn group_body(group: &GroupInfo) -> String {
+    let mut out = String::new();
+    emit!(out, "#[allow(unused_imports)]");
+    emit!(out, "use libnds::animation::Animation;");
+    emit!(out, "#[allow(unused_imports)]");
+    emit!(out, "use libnds::collision::{{Hitbox, Rect}};");
+    emit!(out, "#[allow(unused_imports)]");
+    emit!(out, "use libnds::resources::AssetId;");
+    emit!(out, "#[allow(unused_imports)]");
+    emit!(
+        out,
+        "use libnds::texture::{{BackgroundAsset, Bitmap16Asset, PaletteAsset, SpriteAsset, TiledBackgroundAsset}};"
+    );
+    if let Some(palette_path) = &group.palette_path {
+        emit!(out);
+        emit!(out, "pub static PALETTE: PaletteAsset = PaletteAsset {{");
+        emit!(out, "    id: AssetId::new({palette_path:?}),");
+        emit!(out, "    colors: {},", group.palette_colors);
+        emit!(out, "}};");
+    }
+    for asset in &group.assets {
+        emit!(out);
+        asset_static(&mut out, asset);
+    }
+    out
+}
+
+fn asset_static(out: &mut String, asset: &AssetInfo) {
+    let name = const_name(&asset.stem);
+    emit!(
+        out,
+        "/// `{}`, {}x{} pixels.",
+        asset.stem,
+        asset.width,
+        asset.height
+    );
+    match &asset.kind {
+        AssetKind::Sprite { size } => {
+            emit!(out, "pub static {name}: SpriteAsset = SpriteAsset {{");
+            emit!(out, "    size: libnds::SpriteSize::{size},");
+            let format = match asset.depth {
+                Depth::Bpp8 => "SP256Color",
+                Depth::Bpp4 => "SP16Color",
+            };
+            emit!(out, "    format: libnds::SpriteColorFormat::{format},");
+            emit!(out, "    palette_bank: {},", asset.palette_bank);
+            emit!(out, "    frames: &{:?},", asset.frame_durations);
+            if asset.tags.is_empty() {
+                emit!(out, "    animations: &[],");
+            } else {
+                emit!(out, "    animations: &[");
+                for tag in &asset.tags {
+                    emit!(
+                        out,
+                        "        Animation {{ name: {:?}, from: {}, to: {} }},",
+                        tag.name,
+                        tag.from,
+                        tag.to
+                    );
+                }
+                emit!(out, "    ],");
+            }
+            if let Some(collision) = &asset.collision {
+                collision_fields(out, collision);
+            }
+        }
+        AssetKind::Background { type_, size } if type_ == "Bmp16" => {
+            emit!(out, "pub static {name}: Bitmap16Asset = Bitmap16Asset {{");
+            emit!(out, "    size: libnds::background::{size},");
+        }
+        AssetKind::Background { type_, size } => {
+            let size_ty = size.split("::").next().unwrap();
+            let asset_ty = if asset.map.is_some() {
+                "TiledBackgroundAsset"
+            } else {
+                "BackgroundAsset"
+            };
+            emit!(
+                out,
+                "pub static {name}: {asset_ty}<libnds::background::{size_ty}> = {asset_ty} {{"
+            );
+            emit!(out, "    type_: libnds::background::Type::{type_},");
+            emit!(out, "    size: libnds::background::{size},");
+            if let Some(map) = &asset.map {
+                emit!(out, "    map_id: AssetId::new({:?}),", map.pack_path);
+                emit!(out, "    tile_count: {},", map.tile_count);
+            }
+        }
+    }
+    emit!(out, "    id: AssetId::new({:?}),", asset.pack_path);
+    emit!(out, "    width: {},", asset.width);
+    emit!(out, "    height: {},", asset.height);
+    if !matches!(&asset.kind, AssetKind::Background { type_, .. } if type_ == "Bmp16") {
+        emit!(out, "    palette: &PALETTE,");
+    }
+    emit!(out, "}};");
+}
+
+fn rect(rect: &Rect) -> String {
+    format!(
+        "Rect {{ x: {}, y: {}, width: {}, height: {} }}",
+        rect.x, rect.y, rect.width, rect.height
+    )
+}
+
+fn collision_fields(out: &mut String, collision: &CollisionInfo) {
+    emit!(out, "    mask_id: AssetId::new({:?}),", collision.mask_path);
+    emit!(out, "    bounds: &[");
+    for bounds in &collision.bounds {
+        emit!(out, "        {},", rect(bounds));
+    }
+    emit!(out, "    ],");
+    if collision.hitboxes.is_empty() {
+        emit!(out, "    hitboxes: &[],");
+        return;
+    }
+    emit!(out, "    hitboxes: &[");
+    for hitbox in &collision.hitboxes {
+        emit!(out, "        Hitbox {{");
+        emit!(out, "            name: {:?},", hitbox.name);
+        emit!(out, "            rects: &[");
+        for r in &hitbox.rects {
+            emit!(out, "                {},", rect(r));
+        }
+        emit!(out, "            ],");
+        emit!(out, "        }},");
+    }
+    emit!(out, "    ],");
+}
+
+/// Every OBJ shape the hardware supports, matching the `SpriteSize` variants.
+pub const SPRITE_SIZES: [(u32, u32); 12] = [
+    (8, 8),
+    (16, 16),
+    (32, 32),
+    (64, 64),
+    (16, 8),
+    (32, 8),
+    (32, 16),
+    (64, 32),
+    (8, 16),
+    (8, 32),
+    (16, 32),
+    (32, 64),
+];
+
+/// Every size accepted by `bg::Bitmap8Size`.
+pub const BITMAP8_SIZES: [(u32, u32); 6] = [
+    (128, 128),
+    (256, 256),
+    (512, 256),
+    (512, 512),
+    (1024, 512),
+    (512, 1024),
+];
+
+/// Every size accepted by `bg::Bitmap16Size`.
+pub const BITMAP16_SIZES: [(u32, u32); 4] = [(128, 128), (256, 256), (512, 256), (512, 512)];
+
+/// Every size accepted by `bg::TextSize`.
+pub const TEXT_SIZES: [(u32, u32); 4] = [(256, 256), (512, 256), (256, 512), (512, 512)];
+
+/// Every size accepted by `bg::RotSize` and `bg::ExtRotSize`.
+pub const ROT_SIZES: [(u32, u32); 4] = [(128, 128), (256, 256), (512, 512), (1024, 1024)];
+
+/// Picks the type and size of an asset converted with `format` and `depth`, or explains which
+/// sizes it could have instead.
+pub fn asset_kind(
+    format: Format,
+    depth: Depth,
+    width: u32,
+    height: u32,
+) -> Result<AssetKind, String> {
+    let (what, sizes): (_, &[(u32, u32)]) = match format {
+        Format::Tiled => ("a sprite", &SPRITE_SIZES),
+        Format::Bitmap => ("an 8bpp bitmap background", &BITMAP8_SIZES),
+        Format::Bitmap16 => ("a 16bpp bitmap background", &BITMAP16_SIZES),
+        Format::Map(MapType::Text) => ("a text background", &TEXT_SIZES),
+        Format::Map(MapType::Rotation) => ("a rotation background", &ROT_SIZES),
+        Format::Map(MapType::ExRotation) => ("an extended rotation background", &ROT_SIZES),
+    };
+    if !sizes.contains(&(width, height)) {
+        let nearest: Vec<_> = nearest_sizes(sizes, width, height)
+            .iter()
+            .map(|(w, h)| format!("{w}x{h}"))
+            .collect();
+        return Err(format!(
+            "is {width}x{height}, which {what} can't be. The nearest sizes it can have are {}",
+            nearest.join(", ")
+        ));
+    }
+    let background = |type_: &str, size| AssetKind::Background {
+        type_: type_.into(),
+        size,
+    };
+    Ok(match format {
+        Format::Tiled => AssetKind::Sprite {
+            size: format!("S{width}x{height}"),
+        },
+        Format::Bitmap => background("Bmp8", format!("Bitmap8Size::B8_{width}x{height}")),
+        Format::Bitmap16 => background("Bmp16", format!("Bitmap16Size::B16_{width}x{height}")),
+        Format::Map(MapType::Text) => background(
+            match depth {
+                Depth::Bpp8 => "Text8bpp",
+                Depth::Bpp4 => "Text4bpp",
+            },
+            format!("TextSize::T{width}x{height}"),
+        ),
+        Format::Map(MapType::Rotation) => {
+            background("Rotation", format!("RotSize::R{width}x{height}"))
+        }
+        Format::Map(MapType::ExRotation) => {
+            background("ExRotation", format!("ExtRotSize::ER_{width}x{height}"))
+        }
+    })
+}
+
+/// Up to three of `sizes`, closest to `width`x`height` first, preferring sizes the image fits in
+/// since padding is easier than cropping.
+fn nearest_sizes(sizes: &[(u32, u32)], width: u32, height: u32) -> Vec<(u32, u32)> {
+    let mut sizes = sizes.to_vec();
+    sizes.sort_by_key(|&(w, h)| {
+        let fits = w >= width && h >= height;
+        (w.abs_diff(width) + h.abs_diff(height), !fits, w * h)
+    });
+    sizes.truncate(3);
+    sizes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_constants() {
+        assert_eq!(const_name("Squid"), "SQUID");
+        assert_eq!(const_name("big-rock"), "BIG_ROCK");
+        assert_eq!(const_name("bigRock2"), "BIG_ROCK2");
+        assert_eq!(const_name("1up"), "_1UP");
+    }
+
+    #[test]
+    fn finds_clashing_constants() {
+        let clash = const_name_clash(&["Squid.png", "big-rock.png", "bigRock.pxo", "big_rock.png"]);
+        assert_eq!(
+            clash,
+            Some((Path::new("big-rock.png"), Path::new("bigRock.pxo")))
+        );
+        assert_eq!(const_name_clash(&["Squid.png", "big-rock.png"]), None);
+    }
+}
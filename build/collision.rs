@@ -0,0 +1,132 @@
+//! Collision data of sprites: what `libnds::collision` reads.
+//!
+//! Every frame gets the tight bounds of its opaque pixels and a mask with one bit per pixel,
+//! row by row, the leftmost pixel of each byte in its lowest bit.
+//!
+//! Named rectangles, like where a sprite can be hurt, come from `.pxo` layers named `hitbox...`
+//! or `hurtbox...`, each frame's rectangle bounding the opaque pixels of its cel, and from a
+//! `<stem>.boxes.toml` sidecar next to the image:
+//!
+//! ```toml
+//! hurtbox = [2, 4, 12, 12]               # x, y, width, height in every frame
+//! hitbox = [[0, 0, 0, 0], [10, 4, 6, 4]] # one per frame, empty ones for none
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{AssetError, image::Image};
+
+/// A rectangle in pixels from the top left of a frame, empty when it has no width or height.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    /// Bounds of the opaque pixels of `image`, empty if it has none.
+    pub fn bounds(image: &Image) -> Self {
+        let width = image.width as usize;
+        let opaque = |i: &usize| !Image::is_transparent(image.pixels[*i]);
+        let mut indices = (0..image.pixels.len()).filter(opaque);
+        let Some(first) = indices.next() else {
+            return Self::default();
+        };
+        let (mut left, mut right) = (first % width, first % width);
+        let (top, mut bottom) = (first / width, first / width);
+        for i in indices {
+            left = left.min(i % width);
+            right = right.max(i % width);
+            bottom = i / width;
+        }
+        Self {
+            x: left as u32,
+            y: top as u32,
+            width: (right - left + 1) as u32,
+            height: (bottom - top + 1) as u32,
+        }
+    }
+}
+
+/// A named rectangle in each frame of a sprite.
+#[derive(Serialize, Deserialize)]
+pub struct Hitbox {
+    pub name: String,
+    pub rects: Vec<Rect>,
+}
+
+/// One bit per pixel of `image`, set where it is opaque.
+pub fn mask(image: &Image) -> Vec<u8> {
+    let stride = (image.width as usize).div_ceil(8);
+    let mut out = vec![0; stride * image.height as usize];
+    for (i, &px) in image.pixels.iter().enumerate() {
+        if !Image::is_transparent(px) {
+            let (x, y) = (i % image.width as usize, i / image.width as usize);
+            out[y * stride + x / 8] |= 1 << (x % 8);
+        }
+    }
+    out
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SidecarBox {
+    Still([u32; 4]),
+    Animated(Vec<[u32; 4]>),
+}
+
+/// Path of the sidecar of `source`, e.g. `Squid.boxes.toml` for `Squid.png`.
+pub fn sidecar_path(source: &Path) -> PathBuf {
+    source.with_extension("boxes.toml")
+}
+
+/// Reads the hitboxes of the sidecar at `path`, for a sprite of `frames` frames of
+/// `width`x`height`.
+pub fn read_sidecar(
+    path: &Path,
+    frames: usize,
+    width: u32,
+    height: u32,
+) -> Result<Vec<Hitbox>, AssetError> {
+    let err = |msg: String| AssetError::new(path, msg);
+    let text = std::fs::read_to_string(path).map_err(|e| err(format!("could not open: {e}")))?;
+    let boxes: BTreeMap<String, SidecarBox> =
+        toml::from_str(&text).map_err(|e| err(format!("invalid boxes: {e}")))?;
+    let mut hitboxes = Vec::with_capacity(boxes.len());
+    for (name, b) in boxes {
+        let rects = match b {
+            SidecarBox::Still(rect) => vec![rect; frames],
+            SidecarBox::Animated(rects) if rects.len() == frames => rects,
+            SidecarBox::Animated(rects) => {
+                return Err(err(format!(
+                    "`{name}` has {} rectangles for {frames} frames",
+                    rects.len()
+                )));
+            }
+        };
+        let rects: Vec<Rect> = (rects.into_iter())
+            .map(|[x, y, width, height]| Rect {
+                x,
+                y,
+                width,
+                height,
+            })
+            .collect();
+        let outside = |r: &Rect| r.x + r.width > width || r.y + r.height > height;
+        if let Some(i) = rects.iter().position(outside) {
+            return Err(err(format!(
+                "`{name}` sticks out of frame {} of {width}x{height}",
+                i + 1
+            )));
+        }
+        hitboxes.push(Hitbox { name, rects });
+    }
+    Ok(hitboxes)
+}
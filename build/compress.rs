@@ -0,0 +1,322 @@
+//! LZ77 and RLE compression in the formats the NDS BIOS decompresses.
+//!
+//! A compressed file is [`MAGIC`] followed by the BIOS stream, whose own header holds the type
+//! and the decompressed size. `resources::read` in the wrapper looks for the magic, since the
+//! BIOS header alone could just as well be the start of uncompressed data.
+
+use serde::Deserialize;
+
+pub const MAGIC: &[u8; 4] = b"DHCZ";
+const TYPE_LZ77: u8 = 0x10;
+const TYPE_RLE: u8 = 0x30;
+/// The BIOS header stores the decompressed size in 24 bits.
+const MAX_SIZE: usize = 0xFF_FFFF;
+
+const LZ_MIN_MATCH: usize = 3;
+const LZ_MAX_MATCH: usize = 18;
+const LZ_WINDOW: usize = 4096;
+/// The VRAM variant of the BIOS routine writes 16 bits at a time, so the byte right before the
+/// one being written isn't readable yet.
+const LZ_MIN_DISTANCE: usize = 2;
+/// Candidates tried per position, enough for our assets without slowing down the build.
+const LZ_MAX_CHAIN: usize = 256;
+
+const RLE_MIN_RUN: usize = 3;
+const RLE_MAX_RUN: usize = 130;
+const RLE_MAX_LITERALS: usize = 128;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    None,
+    Lz77,
+    Rle,
+}
+
+/// `data` as written to `romfs/`, compressed if asked to.
+///
+/// The result is decompressed again and compared, so a broken encoder fails the build instead
+/// of shipping garbage.
+pub fn encode(data: &[u8], compression: Compression) -> Result<Vec<u8>, String> {
+    if data.len() > MAX_SIZE {
+        return Err(format!(
+            "{} bytes is too large to compress, the limit is {MAX_SIZE}",
+            data.len()
+        ));
+    }
+    let (kind, stream) = match compression {
+        Compression::None => return Ok(data.to_vec()),
+        Compression::Lz77 => (TYPE_LZ77, lz77(data)),
+        Compression::Rle => (TYPE_RLE, rle(data)),
+    };
+    let mut out = Vec::with_capacity(MAGIC.len() + 4 + stream.len());
+    out.extend(MAGIC);
+    out.extend((kind as u32 | (data.len() as u32) << 8).to_le_bytes());
+    out.extend(stream);
+    out.resize(out.len().next_multiple_of(4), 0);
+
+    if decode(&out).as_deref() != Some(data) {
+        return Err(format!("{compression:?} compression did not round-trip"));
+    }
+    Ok(out)
+}
+
+/// Decompresses the output of [`encode`], `None` if it is malformed.
+pub fn decode(file: &[u8]) -> Option<Vec<u8>> {
+    let header = u32::from_le_bytes(file.strip_prefix(MAGIC)?.get(..4)?.try_into().ok()?);
+    let size = (header >> 8) as usize;
+    let mut stream = file[MAGIC.len() + 4..].iter().copied();
+    let mut out = Vec::with_capacity(size);
+    match header as u8 {
+        TYPE_LZ77 => {
+            while out.len() < size {
+                let flags = stream.next()?;
+                for bit in (0..8).rev() {
+                    if out.len() >= size {
+                        break;
+                    }
+                    if flags & 1 << bit == 0 {
+                        out.push(stream.next()?);
+                        continue;
+                    }
+                    let (hi, lo) = (stream.next()?, stream.next()?);
+                    let len = (hi >> 4) as usize + LZ_MIN_MATCH;
+                    let distance = ((hi as usize & 0xF) << 8 | lo as usize) + 1;
+                    let start = out.len().checked_sub(distance)?;
+                    for i in start..start + len {
+                        out.push(out[i]);
+                    }
+                }
+            }
+        }
+        TYPE_RLE => {
+            while out.len() < size {
+                let flag = stream.next()?;
+                if flag & 0x80 != 0 {
+                    let byte = stream.next()?;
+                    let len = (flag & 0x7F) as usize + RLE_MIN_RUN;
+                    out.extend(std::iter::repeat_n(byte, len));
+                } else {
+                    for _ in 0..(flag as usize + 1) {
+                        out.push(stream.next()?);
+                    }
+                }
+            }
+        }
+        _ => return None,
+    }
+    out.truncate(size);
+    Some(out)
+}
+
+/// LZ77 stream without its header: groups of 8 blocks, each group preceded by a flag byte whose
+/// bits, most significant first, tell literals (0) from back-references (1).
+fn lz77(data: &[u8]) -> Vec<u8> {
+    // Positions where each 3 byte sequence was last seen, chained to the previous ones.
+    let hash = |i: usize| {
+        ((data[i] as usize) << 8 ^ (data[i + 1] as usize) << 4 ^ data[i + 2] as usize) & 0xFFF
+    };
+    let mut head = vec![usize::MAX; 1 << 12];
+    let mut prev = vec![usize::MAX; data.len()];
+    let insert = |i: usize, head: &mut [usize], prev: &mut [usize]| {
+        if i + LZ_MIN_MATCH <= data.len() {
+            let h = hash(i);
+            prev[i] = head[h];
+            head[h] = i;
+        }
+    };
+
+    let mut out = vec![];
+    let mut flags_at = 0;
+    let mut i = 0;
+    let mut block = 0;
+    while i < data.len() {
+        if block % 8 == 0 {
+            flags_at = out.len();
+            out.push(0);
+        }
+        let (mut best_len, mut best_distance) = (0, 0);
+        if i + LZ_MIN_MATCH <= data.len() {
+            let max_len = LZ_MAX_MATCH.min(data.len() - i);
+            let mut candidate = head[hash(i)];
+            let mut tries = 0;
+            while candidate != usize::MAX && tries < LZ_MAX_CHAIN {
+                let distance = i - candidate;
+                if distance > LZ_WINDOW {
+                    break;
+                }
+                if distance >= LZ_MIN_DISTANCE {
+                    let len = (0..max_len)
+                        .take_while(|&k| data[candidate + k] == data[i + k])
+                        .count();
+                    if len > best_len {
+                        (best_len, best_distance) = (len, distance);
+                        if len == max_len {
+                            break;
+                        }
+                    }
+                }
+                candidate = prev[candidate];
+                tries += 1;
+            }
+        }
+
+        if best_len >= LZ_MIN_MATCH {
+            out[flags_at] |= 0x80 >> (block % 8);
+            let encoded = (best_len - LZ_MIN_MATCH) << 12 | (best_distance - 1);
+            out.extend((encoded as u16).to_be_bytes());
+            for k in i..i + best_len {
+                insert(k, &mut head, &mut prev);
+            }
+            i += best_len;
+        } else {
+            out.push(data[i]);
+            insert(i, &mut head, &mut prev);
+            i += 1;
+        }
+        block += 1;
+    }
+    out
+}
+
+/// RLE stream without its header: blocks of a flag byte, then either a byte repeated
+/// `(flag & 0x7F) + 3` times if bit 7 is set, or `flag + 1` literal bytes.
+fn rle(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    let mut literals: Vec<u8> = vec![];
+    let flush = |literals: &mut Vec<u8>, out: &mut Vec<u8>| {
+        for chunk in literals.chunks(RLE_MAX_LITERALS) {
+            out.push(chunk.len() as u8 - 1);
+            out.extend(chunk);
+        }
+        literals.clear();
+    };
+    let mut i = 0;
+    while i < data.len() {
+        let run = data[i..]
+            .iter()
+            .take(RLE_MAX_RUN)
+            .take_while(|&&b| b == data[i])
+            .count();
+        if run >= RLE_MIN_RUN {
+            flush(&mut literals, &mut out);
+            out.extend([0x80 | (run - RLE_MIN_RUN) as u8, data[i]]);
+            i += run;
+        } else {
+            literals.push(data[i]);
+            i += 1;
+        }
+    }
+    flush(&mut literals, &mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(data: &[u8], compression: Compression) {
+        let file = encode(data, compression).unwrap();
+        assert_eq!(decode(&file).as_deref(), Some(data), "{compression:?}");
+    }
+
+    /// Length and distance of every back-reference of an LZ77 stream.
+    fn references(stream: &[u8]) -> Vec<(usize, usize)> {
+        let mut refs = vec![];
+        let mut i = 0;
+        while i < stream.len() {
+            let flags = stream[i];
+            i += 1;
+            for bit in (0..8).rev() {
+                if i >= stream.len() {
+                    break;
+                }
+                if flags & 1 << bit == 0 {
+                    i += 1;
+                    continue;
+                }
+                let (hi, lo) = (stream[i], stream[i + 1]);
+                let distance = ((hi as usize & 0xF) << 8 | lo as usize) + 1;
+                refs.push(((hi >> 4) as usize + LZ_MIN_MATCH, distance));
+                i += 2;
+            }
+        }
+        refs
+    }
+
+    /// Bytes without runs or repeats worth encoding, from a linear congruential generator.
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545_F491_u32;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 24) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn empty_input_round_trips() {
+        round_trip(&[], Compression::Lz77);
+        round_trip(&[], Compression::Rle);
+    }
+
+    #[test]
+    fn rle_splits_long_runs() {
+        let data = [7; 300];
+        round_trip(&data, Compression::Rle);
+        // 130 + 130 + 40 bytes.
+        assert_eq!(rle(&data), [0xFF, 7, 0xFF, 7, 0x80 | (40 - 3), 7]);
+    }
+
+    #[test]
+    fn rle_splits_long_literals() {
+        let data = noise(300);
+        round_trip(&data, Compression::Rle);
+        let stream = rle(&data);
+        assert_eq!(stream.len(), data.len() + 3);
+        assert_eq!([stream[0], stream[129], stream[258]], [127, 127, 43]);
+    }
+
+    #[test]
+    fn lz77_splits_long_matches() {
+        let data: Vec<u8> = b"0123456789".iter().copied().cycle().take(200).collect();
+        round_trip(&data, Compression::Lz77);
+        let refs = references(&lz77(&data));
+        assert!(refs.iter().all(|&(len, _)| len <= LZ_MAX_MATCH));
+        assert_eq!(refs.iter().map(|(len, _)| len).sum::<usize>(), 190);
+    }
+
+    #[test]
+    fn lz77_overlaps_at_the_minimum_distance() {
+        let data: Vec<u8> = b"ab".iter().copied().cycle().take(40).collect();
+        round_trip(&data, Compression::Lz77);
+        let refs = references(&lz77(&data));
+        assert!(refs.iter().all(|&(_, distance)| distance == 2), "{refs:?}");
+        assert!(
+            refs.iter().any(|&(len, _)| len > 2),
+            "no overlapping reference"
+        );
+
+        // A run would be a reference to the previous byte, which the VRAM variant can't read.
+        let run = [5; 40];
+        round_trip(&run, Compression::Lz77);
+        let refs = references(&lz77(&run));
+        assert!(!refs.is_empty());
+        assert!(
+            refs.iter()
+                .all(|&(_, distance)| distance >= LZ_MIN_DISTANCE)
+        );
+    }
+
+    #[test]
+    fn incompressible_data_round_trips() {
+        let data = noise(5000);
+        round_trip(&data, Compression::Lz77);
+        round_trip(&data, Compression::Rle);
+        // One flag byte per 8 literals, and per 128 literals.
+        assert!(lz77(&data).len() <= data.len() + data.len().div_ceil(8));
+        assert!(rle(&data).len() <= data.len() + data.len().div_ceil(128));
+    }
+}
@@ -0,0 +1,328 @@
+//! Font conversion: BDF bitmap fonts as they are, TrueType and OpenType fonts rasterized at the
+//! pixel size set in the manifest.
+//!
+//! A `.fnt` file is, all integers little endian:
+//!
+//! - A 16 byte header: [`MAGIC`], [`VERSION`] (u8), bits per pixel (u8, 1 or 4), glyph count
+//!   (u16), line height (u8), ascent (u8), index of the glyph drawn for missing characters (u16,
+//!   `0xFFFF` for none) and the size of the glyph data (u32).
+//! - 16 bytes per glyph, sorted by codepoint: the codepoint (u32), the offset of its pixels in the
+//!   glyph data (u32), width and height (u8), left and top bearings (i8) and advance (u8), then 3
+//!   bytes of padding. The top bearing is from the top of the line, so a glyph row `y` is drawn
+//!   `top + y` pixels below it.
+//! - The glyph data, every glyph's pixels row by row, each row starting on a byte. Pixels are
+//!   coverage levels, 0 or 1 in 1bpp fonts and 0 to 15 in 4bpp ones, leftmost pixel in the lowest
+//!   bits like the DS's own 4bpp graphics.
+
+use std::path::Path;
+
+use crate::{AssetError, manifest::AssetOptions};
+
+pub const MAGIC: &[u8; 4] = b"DHFN";
+pub const VERSION: u8 = 1;
+/// Characters rasterized from TrueType and OpenType fonts, printable ASCII and Latin-1.
+const OUTLINE_CHARS: [std::ops::RangeInclusive<char>; 2] = [' '..='~', '\u{A0}'..='\u{FF}'];
+
+pub struct Font {
+    pub name: String,
+    /// Pixels from the top of a line to the baseline.
+    pub ascent: u32,
+    /// Pixels from the baseline to the bottom of a line.
+    pub descent: u32,
+    pub bpp: u8,
+    /// Sorted by codepoint.
+    pub glyphs: Vec<Glyph>,
+    /// Drawn in place of characters the font lacks.
+    pub default_char: Option<char>,
+}
+
+pub struct Glyph {
+    pub c: char,
+    pub width: u32,
+    pub height: u32,
+    pub left: i32,
+    /// From the top of the line.
+    pub top: i32,
+    pub advance: u32,
+    /// Coverage from 0 to 255, row by row.
+    pub coverage: Vec<u8>,
+}
+
+impl Font {
+    pub fn open(path: &Path, options: &AssetOptions) -> Result<Self, AssetError> {
+        let err = |msg: String| AssetError::new(path, msg);
+        let bytes = std::fs::read(path).map_err(|e| err(format!("could not open: {e}")))?;
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        let is_bdf = path.extension().is_some_and(|ext| ext == "bdf");
+        let mut font = if is_bdf {
+            if options.font_size.is_some() {
+                return Err(err("`font_size` only applies to outline fonts".into()));
+            }
+            let text = String::from_utf8(bytes).map_err(|_| err("is not UTF-8".into()))?;
+            parse_bdf(name, &text).map_err(err)?
+        } else {
+            let size = options.font_size.ok_or_else(|| {
+                err(format!(
+                    "needs a `font_size` in {}",
+                    crate::manifest::FILE_NAME
+                ))
+            })?;
+            rasterize(name, &bytes, size).map_err(err)?
+        };
+        font.bpp = match options.font_bpp {
+            None if is_bdf => 1,
+            None => 4,
+            Some(bpp @ (1 | 4)) => bpp,
+            Some(bpp) => return Err(err(format!("`font_bpp` is {bpp}, it can be 1 or 4"))),
+        };
+        font.glyphs.sort_by_key(|g| g.c);
+        font.glyphs.dedup_by_key(|g| g.c);
+        Ok(font)
+    }
+
+    pub fn line_height(&self) -> u32 {
+        self.ascent + self.descent
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, String> {
+        let byte = |value: i32, what: &str, c: char| {
+            i8::try_from(value)
+                .map(|v| v as u8)
+                .map_err(|_| format!("the {what} of {c:?} is too large"))
+        };
+        let line_height = u8::try_from(self.line_height())
+            .map_err(|_| format!("a line height of {} is too tall", self.line_height()))?;
+        let count = u16::try_from(self.glyphs.len())
+            .map_err(|_| format!("{} glyphs is too many", self.glyphs.len()))?;
+        let default = self
+            .default_char
+            .and_then(|c| self.glyphs.iter().position(|g| g.c == c))
+            .map_or(0xFFFF, |i| i as u16);
+
+        let mut table = vec![];
+        let mut data = vec![];
+        for glyph in &self.glyphs {
+            let (width, height) = (
+                u8::try_from(glyph.width).map_err(|_| format!("{:?} is too wide", glyph.c))?,
+                u8::try_from(glyph.height).map_err(|_| format!("{:?} is too tall", glyph.c))?,
+            );
+            table.extend((glyph.c as u32).to_le_bytes());
+            table.extend((data.len() as u32).to_le_bytes());
+            table.extend([
+                width,
+                height,
+                byte(glyph.left, "left bearing", glyph.c)?,
+                byte(glyph.top, "top bearing", glyph.c)?,
+                u8::try_from(glyph.advance)
+                    .map_err(|_| format!("the advance of {:?} is too large", glyph.c))?,
+                0,
+                0,
+                0,
+            ]);
+            for row in glyph.coverage.chunks(glyph.width.max(1) as usize) {
+                data.extend(pack_row(row, self.bpp));
+            }
+        }
+
+        let mut out = Vec::with_capacity(16 + table.len() + data.len());
+        out.extend(MAGIC);
+        out.extend([VERSION, self.bpp]);
+        out.extend(count.to_le_bytes());
+        out.extend([line_height, self.ascent as u8]);
+        out.extend(default.to_le_bytes());
+        out.extend((data.len() as u32).to_le_bytes());
+        out.extend(table);
+        out.extend(data);
+        Ok(out)
+    }
+}
+
+/// Quantizes a row of coverage to `bpp` bits per pixel, leftmost pixel in the lowest bits.
+fn pack_row(row: &[u8], bpp: u8) -> Vec<u8> {
+    let per_byte = 8 / bpp as usize;
+    row.chunks(per_byte)
+        .map(|pixels| {
+            pixels.iter().enumerate().fold(0, |byte, (i, &coverage)| {
+                let level = match bpp {
+                    1 => (coverage >= 0x80) as u8,
+                    _ => coverage >> 4,
+                };
+                byte | level << (i * bpp as usize)
+            })
+        })
+        .collect()
+}
+
+/// Parses the glyphs of a BDF font, see Adobe's Glyph Bitmap Distribution Format spec.
+fn parse_bdf(name: String, text: &str) -> Result<Font, String> {
+    let mut font = Font {
+        name,
+        ascent: 0,
+        descent: 0,
+        bpp: 1,
+        glyphs: vec![],
+        default_char: None,
+    };
+    let mut lines = text.lines().enumerate();
+    let mut bounding_box = None;
+    while let Some((number, line)) = lines.next() {
+        let err = |msg: &str| format!("line {}: {msg}", number + 1);
+        let mut words = line.split_whitespace();
+        let numbers = |words: std::str::SplitWhitespace| -> Result<Vec<i32>, String> {
+            words
+                .map(|w| w.parse().map_err(|_| err(&format!("bad number `{w}`"))))
+                .collect()
+        };
+        match words.next() {
+            Some("FONTBOUNDINGBOX") => {
+                if let [w, h, x, y] = numbers(words)?[..] {
+                    bounding_box = Some((w, h, x, y));
+                }
+            }
+            Some("FONT_ASCENT") => {
+                font.ascent = numbers(words)?.first().copied().unwrap_or(0) as u32
+            }
+            Some("FONT_DESCENT") => {
+                font.descent = numbers(words)?.first().copied().unwrap_or(0) as u32
+            }
+            Some("DEFAULT_CHAR") => {
+                font.default_char = numbers(words)?
+                    .first()
+                    .and_then(|&c| char::from_u32(c as u32));
+            }
+            Some("STARTCHAR") => {
+                if let Some(glyph) = parse_bdf_glyph(&mut lines)? {
+                    font.glyphs.push(glyph);
+                }
+            }
+            _ => {}
+        }
+    }
+    // Fonts without the properties fall back to their bounding box.
+    if font.ascent == 0 && font.descent == 0 {
+        let (_, h, _, y) = bounding_box.ok_or("has neither FONT_ASCENT nor FONTBOUNDINGBOX")?;
+        font.ascent = (h + y).max(0) as u32;
+        font.descent = (-y).max(0) as u32;
+    }
+    if font.glyphs.is_empty() {
+        return Err("has no glyphs".into());
+    }
+    // Glyphs were positioned from the baseline, the ascent wasn't necessarily known yet.
+    for glyph in &mut font.glyphs {
+        glyph.top += font.ascent as i32;
+    }
+    font.default_char = font.default_char.or(Some('?'));
+    Ok(font)
+}
+
+/// Parses the glyph whose `STARTCHAR` was just read, `None` if it has no Unicode encoding.
+///
+/// Its top bearing is from the baseline rather than the top of the line.
+fn parse_bdf_glyph<'a>(
+    lines: &mut impl Iterator<Item = (usize, &'a str)>,
+) -> Result<Option<Glyph>, String> {
+    let mut c = None;
+    let mut advance = 0;
+    let mut bbx = (0, 0, 0, 0);
+    let mut coverage = vec![];
+    let mut in_bitmap = false;
+    for (number, line) in lines.by_ref() {
+        let err = |msg: &str| format!("line {}: {msg}", number + 1);
+        let number = |w: Option<&str>| -> Result<i32, String> {
+            let w = w.ok_or_else(|| err("missing number"))?;
+            w.parse().map_err(|_| err(&format!("bad number `{w}`")))
+        };
+        let mut words = line.split_whitespace();
+        let keyword = words.next().unwrap_or_default();
+        if in_bitmap && keyword != "ENDCHAR" {
+            let (width, ..) = bbx;
+            let row = u64::from_str_radix(keyword, 16)
+                .map_err(|_| err(&format!("bad bitmap row `{keyword}`")))?;
+            let bits = keyword.len() as u32 * 4;
+            if (width as u32) > bits {
+                return Err(err("bitmap row is narrower than the glyph"));
+            }
+            coverage.extend((0..width as u32).map(|x| {
+                if row >> (bits - 1 - x) & 1 != 0 {
+                    0xFF
+                } else {
+                    0
+                }
+            }));
+            continue;
+        }
+        match keyword {
+            // An encoding of -1 means the glyph isn't mapped to a character.
+            "ENCODING" => {
+                c = u32::try_from(number(words.next())?)
+                    .ok()
+                    .and_then(char::from_u32)
+            }
+            "DWIDTH" => advance = number(words.next())?,
+            "BBX" => {
+                bbx = (
+                    number(words.next())?,
+                    number(words.next())?,
+                    number(words.next())?,
+                    number(words.next())?,
+                )
+            }
+            "BITMAP" => in_bitmap = true,
+            "ENDCHAR" => {
+                let (width, height, x, y) = bbx;
+                if coverage.len() != (width * height) as usize {
+                    return Err(err("bitmap does not match the glyph's BBX"));
+                }
+                return Ok(c.map(|c| Glyph {
+                    c,
+                    width: width as u32,
+                    height: height as u32,
+                    left: x,
+                    top: -(y + height),
+                    advance: advance.max(0) as u32,
+                    coverage,
+                }));
+            }
+            _ => {}
+        }
+    }
+    Err("a glyph has no ENDCHAR".into())
+}
+
+/// Rasterizes the characters of [`OUTLINE_CHARS`] the font has at `size` pixels.
+fn rasterize(name: String, bytes: &[u8], size: u32) -> Result<Font, String> {
+    let outline = fontdue::Font::from_bytes(bytes, fontdue::FontSettings::default())
+        .map_err(|e| format!("invalid font: {e}"))?;
+    let px = size as f32;
+    let metrics = outline
+        .horizontal_line_metrics(px)
+        .ok_or("has no horizontal metrics")?;
+    let ascent = metrics.ascent.ceil() as i32;
+    let mut glyphs = vec![];
+    for c in OUTLINE_CHARS.into_iter().flatten() {
+        if !outline.has_glyph(c) {
+            continue;
+        }
+        let (glyph, coverage) = outline.rasterize(c, px);
+        glyphs.push(Glyph {
+            c,
+            width: glyph.width as u32,
+            height: glyph.height as u32,
+            left: glyph.xmin,
+            top: ascent - (glyph.ymin + glyph.height as i32),
+            advance: glyph.advance_width.round() as u32,
+            coverage,
+        });
+    }
+    if glyphs.is_empty() {
+        return Err("has none of the characters to convert".into());
+    }
+    Ok(Font {
+        name,
+        ascent: ascent as u32,
+        descent: (-metrics.descent).ceil() as u32,
+        bpp: 4,
+        glyphs,
+        default_char: Some('?'),
+    })
+}
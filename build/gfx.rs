@@ -0,0 +1,131 @@
+use crate::{
+    image::Image,
+    palette::{Depth, Palette},
+};
+
+pub const TILE_SIZE: usize = 8;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Format {
+    /// 8x8 tiles stored one after the other, left to right, top to bottom.
+    /// This is what `Gfx::set_texture` expects for 1D sprite mappings.
+    Tiled,
+    /// One byte per pixel, row by row. This is what `Bmp8` backgrounds expect.
+    Bitmap,
+    /// ABGR1555 halfwords, row by row, without a palette. This is what `Bmp16` backgrounds
+    /// expect.
+    Bitmap16,
+    /// Deduplicated tiles plus a map of them, for tiled backgrounds.
+    Map(MapType),
+}
+
+/// The kind of tiled background a map is made for.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum MapType {
+    /// `Text8bpp` or `Text4bpp`, 16 bit entries split into 32x32 screen blocks.
+    Text,
+    /// `Rotation`, 8 bit entries with no flips nor palette bank.
+    Rotation,
+    /// `ExRotation`, 16 bit entries like text backgrounds but in one block.
+    ExRotation,
+}
+
+/// An image converted to palette indices, one per byte.
+pub struct Indexed {
+    pub width: usize,
+    pub height: usize,
+    pub indices: Vec<u8>,
+}
+
+impl Indexed {
+    /// Maps every pixel of `image` to its index in `palette`, or in `bank` for 4bpp images.
+    ///
+    /// The palette must already contain every color of the image.
+    pub fn new(image: &Image, palette: &Palette, bank: Option<u8>) -> Self {
+        let indices = image
+            .pixels
+            .iter()
+            .map(|&px| {
+                palette
+                    .index_of(px, bank)
+                    .expect("image colors were added to the palette")
+            })
+            .collect();
+        Self {
+            width: image.width as usize,
+            height: image.height as usize,
+            indices,
+        }
+    }
+
+    /// Encodes the image as `Tiled` or `Bitmap`, see `Tileset` for `Map` and [`direct_color`]
+    /// for `Bitmap16`.
+    pub fn encode(&self, format: Format, depth: Depth) -> Result<Vec<u8>, String> {
+        let data = match format {
+            Format::Bitmap => self.indices.clone(),
+            Format::Tiled => self.tiles()?,
+            Format::Map(_) => unreachable!("maps are built with `Tileset::add`"),
+            Format::Bitmap16 => unreachable!("direct color bitmaps aren't indexed"),
+        };
+        Ok(pack(data, depth))
+    }
+
+    pub fn tiles(&self) -> Result<Vec<u8>, String> {
+        if !self.width.is_multiple_of(TILE_SIZE) || !self.height.is_multiple_of(TILE_SIZE) {
+            return Err(format!(
+                "{}x{} is not a multiple of the {TILE_SIZE}x{TILE_SIZE} tile size",
+                self.width, self.height
+            ));
+        }
+        let mut out = Vec::with_capacity(self.indices.len());
+        for ty in (0..self.height).step_by(TILE_SIZE) {
+            for tx in (0..self.width).step_by(TILE_SIZE) {
+                for y in ty..ty + TILE_SIZE {
+                    let row = y * self.width + tx;
+                    out.extend_from_slice(&self.indices[row..row + TILE_SIZE]);
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// 4x4 Bayer matrix, the thresholds of ordered dithering.
+const BAYER: [[u32; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Encodes `image` as ABGR1555 halfwords, transparent pixels having the alpha bit cleared.
+///
+/// Without `dither` channels are truncated to 5 bits, which bands smooth gradients. With it the
+/// dropped bits become an ordered dither pattern, which keeps the average color.
+pub fn direct_color(image: &Image, dither: bool) -> Vec<u8> {
+    let width = image.width as usize;
+    let mut out = Vec::with_capacity(image.pixels.len() * 2);
+    for (i, &px) in image.pixels.iter().enumerate() {
+        if Image::is_transparent(px) {
+            out.extend([0, 0]);
+            continue;
+        }
+        let threshold = if dither {
+            BAYER[i / width % 4][i % width % 4]
+        } else {
+            0
+        };
+        // Adds up to 15/16 of the 8 values a 5 bit step spans before dropping 3 bits.
+        let [r, g, b] = [px[0], px[1], px[2]].map(|c| ((c as u32 * 2 + threshold) / 16).min(31));
+        let color = 0x8000 | b << 10 | g << 5 | r;
+        out.extend((color as u16).to_le_bytes());
+    }
+    out
+}
+
+/// Packs one index per byte into the layout of `depth`.
+pub fn pack(data: Vec<u8>, depth: Depth) -> Vec<u8> {
+    match depth {
+        Depth::Bpp8 => data,
+        // The leftmost pixel goes in the low nibble.
+        Depth::Bpp4 => data
+            .chunks(2)
+            .map(|p| p[0] | p.get(1).unwrap_or(&0) << 4)
+            .collect(),
+    }
+}
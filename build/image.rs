@@ -0,0 +1,73 @@
+use std::{fs::File, io::BufReader, path::Path};
+
+use png::{ColorType, Transformations};
+
+use crate::AssetError;
+
+/// Color treated as transparent, same as grit's `-gT FF00FF`.
+pub const COLOR_KEY: [u8; 3] = [0xFF, 0x00, 0xFF];
+
+/// A decoded RGBA8 image. Color-keyed pixels have their alpha cleared.
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[u8; 4]>,
+}
+
+impl Image {
+    pub fn open(path: &Path) -> Result<Self, AssetError> {
+        let err = |msg: String| AssetError::new(path, msg);
+        let file = File::open(path).map_err(|e| err(format!("could not open: {e}")))?;
+        let mut decoder = png::Decoder::new(BufReader::new(file));
+        // Expands paletted, grayscale and low bit depth images, and strips 16 bit channels.
+        decoder.set_transformations(Transformations::normalize_to_color8());
+        let mut reader = decoder
+            .read_info()
+            .map_err(|e| err(format!("invalid png: {e}")))?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader
+            .next_frame(&mut buf)
+            .map_err(|e| err(format!("invalid png: {e}")))?;
+        let bytes = &buf[..info.buffer_size()];
+
+        let pixels = match info.color_type {
+            ColorType::Rgba => bytes
+                .chunks_exact(4)
+                .map(|p| [p[0], p[1], p[2], p[3]])
+                .collect(),
+            ColorType::Rgb => bytes
+                .chunks_exact(3)
+                .map(|p| [p[0], p[1], p[2], 0xFF])
+                .collect(),
+            ColorType::GrayscaleAlpha => bytes
+                .chunks_exact(2)
+                .map(|p| [p[0], p[0], p[0], p[1]])
+                .collect(),
+            ColorType::Grayscale => bytes.iter().map(|&p| [p, p, p, 0xFF]).collect(),
+            ColorType::Indexed => unreachable!("palette is expanded by the decoder"),
+        };
+        Ok(Self::from_rgba(info.width, info.height, pixels))
+    }
+
+    pub fn from_rgba(width: u32, height: u32, pixels: Vec<[u8; 4]>) -> Self {
+        let mut image = Self {
+            width,
+            height,
+            pixels,
+        };
+        image.apply_color_key(COLOR_KEY);
+        image
+    }
+
+    pub fn apply_color_key(&mut self, key: [u8; 3]) {
+        for px in &mut self.pixels {
+            if px[..3] == key {
+                px[3] = 0;
+            }
+        }
+    }
+
+    pub fn is_transparent(px: [u8; 4]) -> bool {
+        px[3] < 0x80
+    }
+}
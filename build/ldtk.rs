@@ -0,0 +1,237 @@
+//! LDtk `.ldtk` project import.
+//!
+//! Every level of the project is converted, named after its identifier. Levels must be saved in
+//! the project file, not in separate `.ldtkl` files.
+//!
+//! The IntGrid layer named `Collision` becomes the collision grid, keeping its values. Auto-layer
+//! tiles of IntGrid layers, including that one, are drawn like any other tile layer.
+
+use std::{collections::HashMap, path::Path};
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+    AssetError,
+    image::Image,
+    level::{self, Collision, Entity, Flip, Level, Property},
+};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProjectJson {
+    levels: Vec<LevelJson>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LevelJson {
+    identifier: String,
+    px_wid: u32,
+    px_hei: u32,
+    /// `None` when the level is saved in its own file.
+    layer_instances: Option<Vec<LayerJson>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LayerJson {
+    #[serde(rename = "__identifier")]
+    identifier: String,
+    #[serde(rename = "__type")]
+    kind: String,
+    #[serde(rename = "__gridSize")]
+    grid_size: u32,
+    #[serde(rename = "__cWid")]
+    c_wid: u32,
+    #[serde(rename = "__cHei")]
+    c_hei: u32,
+    #[serde(rename = "__pxTotalOffsetX")]
+    offset_x: i32,
+    #[serde(rename = "__pxTotalOffsetY")]
+    offset_y: i32,
+    /// Relative to the project file.
+    #[serde(rename = "__tilesetRelPath")]
+    tileset_path: Option<String>,
+    visible: bool,
+    #[serde(default)]
+    int_grid_csv: Vec<u32>,
+    #[serde(default)]
+    grid_tiles: Vec<TileJson>,
+    #[serde(default)]
+    auto_layer_tiles: Vec<TileJson>,
+    #[serde(default)]
+    entity_instances: Vec<EntityJson>,
+}
+
+#[derive(Deserialize)]
+struct TileJson {
+    /// Position in the layer.
+    px: [i32; 2],
+    /// Position in the tileset.
+    src: [u32; 2],
+    /// Bit 0 flips horizontally, bit 1 vertically.
+    f: u8,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EntityJson {
+    #[serde(rename = "__identifier")]
+    identifier: String,
+    /// Position of the pivot.
+    px: [i32; 2],
+    #[serde(rename = "__pivot")]
+    pivot: [f32; 2],
+    width: u32,
+    height: u32,
+    field_instances: Vec<FieldJson>,
+}
+
+#[derive(Deserialize)]
+struct FieldJson {
+    #[serde(rename = "__identifier")]
+    identifier: String,
+    #[serde(rename = "__type")]
+    kind: String,
+    #[serde(rename = "__value")]
+    value: Value,
+}
+
+pub fn open(path: &Path) -> Result<Vec<Level>, AssetError> {
+    let err = |msg: String| AssetError::new(path, msg);
+    let text = std::fs::read(path).map_err(|e| err(format!("could not open: {e}")))?;
+    let json: ProjectJson =
+        serde_json::from_slice(&text).map_err(|e| err(format!("invalid ldtk project: {e}")))?;
+
+    let dir = path.parent().unwrap();
+    let mut tilesets: HashMap<String, Image> = HashMap::new();
+    let mut levels = Vec::with_capacity(json.levels.len());
+    for level_json in json.levels {
+        let err = |msg: String| err(format!("level `{}` {msg}", level_json.identifier));
+        let Some(layers) = level_json.layer_instances else {
+            return Err(err(
+                "is saved in a separate file, disable that in the project settings".into(),
+            ));
+        };
+        let mut level = Level::new(
+            level_json.identifier.clone(),
+            level_json.px_wid,
+            level_json.px_hei,
+        );
+        // LDtk lists layers top to bottom, like `Level::layers`.
+        for layer in layers.iter().filter(|l| l.visible) {
+            let err = |msg: String| err(format!("layer `{}` {msg}", layer.identifier));
+            match layer.kind.as_str() {
+                "IntGrid" if layer.identifier.eq_ignore_ascii_case("collision") => {
+                    if level.collision.is_some() {
+                        return Err(err("is a second collision layer".into()));
+                    }
+                    level.collision = Some(Collision {
+                        cell_width: layer.grid_size,
+                        cell_height: layer.grid_size,
+                        cols: layer.c_wid,
+                        rows: layer.c_hei,
+                        cells: layer
+                            .int_grid_csv
+                            .iter()
+                            .map(|&v| v.min(u8::MAX as u32) as u8)
+                            .collect(),
+                    });
+                }
+                "Entities" => {
+                    for entity in &layer.entity_instances {
+                        level.entities.push(entity_from_json(entity).map_err(err)?);
+                    }
+                }
+                _ => {}
+            }
+
+            let tiles = match layer.kind.as_str() {
+                "Tiles" => &layer.grid_tiles,
+                _ => &layer.auto_layer_tiles,
+            };
+            if tiles.is_empty() {
+                continue;
+            }
+            let Some(tileset_path) = &layer.tileset_path else {
+                return Err(err("has tiles but no tileset".into()));
+            };
+            if !tilesets.contains_key(tileset_path) {
+                let image_path = dir.join(tileset_path);
+                crate::cache::track(&image_path);
+                tilesets.insert(tileset_path.clone(), Image::open(&image_path)?);
+            }
+            let tileset = &tilesets[tileset_path];
+            let mut canvas = level.canvas();
+            for tile in tiles {
+                let flip = Flip {
+                    h: tile.f & 1 != 0,
+                    v: tile.f & 2 != 0,
+                    diagonal: false,
+                };
+                let pos = (tile.px[0] + layer.offset_x, tile.px[1] + layer.offset_y);
+                let size = (layer.grid_size, layer.grid_size);
+                level::draw_tile(
+                    &mut canvas,
+                    pos,
+                    tileset,
+                    (tile.src[0], tile.src[1]),
+                    size,
+                    flip,
+                );
+            }
+            level.layers.push(canvas);
+        }
+        levels.push(level);
+    }
+    Ok(levels)
+}
+
+fn entity_from_json(entity: &EntityJson) -> Result<Entity, String> {
+    let mut properties = vec![];
+    for field in &entity.field_instances {
+        let bad = || {
+            format!(
+                "has a bad value for field `{}` of entity `{}`",
+                field.identifier, entity.identifier
+            )
+        };
+        let value = &field.value;
+        let property = match field.kind.as_str() {
+            // Unset optional fields are left out, the game gets to pick a default.
+            _ if value.is_null() => continue,
+            "Int" => {
+                let value = value.as_i64().ok_or_else(bad)?;
+                Property::Int(i32::try_from(value).map_err(|_| bad())?)
+            }
+            "Float" => Property::Float(value.as_f64().ok_or_else(bad)? as f32),
+            "Bool" => Property::Bool(value.as_bool().ok_or_else(bad)?),
+            "String" | "Multilines" | "Color" | "FilePath" => {
+                Property::String(value.as_str().ok_or_else(bad)?.to_owned())
+            }
+            kind if kind.starts_with("LocalEnum.") || kind.starts_with("ExternEnum.") => {
+                Property::String(value.as_str().ok_or_else(bad)?.to_owned())
+            }
+            kind => {
+                return Err(format!(
+                    "has field `{}` of unsupported type {kind} on entity `{}`",
+                    field.identifier, entity.identifier
+                ));
+            }
+        };
+        properties.push((field.identifier.clone(), property));
+    }
+
+    // Entities are stored by their top left corner, whatever the pivot.
+    let x = entity.px[0] as f32 - entity.pivot[0] * entity.width as f32;
+    let y = entity.px[1] as f32 - entity.pivot[1] * entity.height as f32;
+    Ok(Entity {
+        kind: entity.identifier.clone(),
+        x: x.round() as i32,
+        y: y.round() as i32,
+        width: entity.width,
+        height: entity.height,
+        properties,
+    })
+}
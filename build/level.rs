@@ -0,0 +1,264 @@
+//! Levels imported from Tiled and LDtk, converted into the `.lvl` files read by `src/level.rs`.
+//!
+//! Everything is little endian and every section starts 4 byte aligned:
+//!
+//! - header: `b"DHLV"`, version `u8`, layer count `u8`, entity count `u16`, width and height in
+//!   pixels `u16`, collision cell width and height `u8`, collision columns and rows `u16`, tile
+//!   count `u16`
+//! - palette: 256 colors, like `pal.bin`
+//! - tiles: 8bpp tiles shared by every layer
+//! - layers, topmost first: index of the `bg::TextSize` variant `u8`, 3 bytes of padding, then
+//!   the map
+//! - collision: one `u8` per cell, row by row, 0 for empty ones
+//! - entities: kind, x and y of the top left corner `i16`, width and height `u16`, property count
+//!   `u8`, then each property as its name, a type `u8` and a value: `i32` (0), `f32` (1),
+//!   bool `u8` (2) or string (3)
+//!
+//! Strings are a `u8` length followed by that many bytes of UTF-8.
+
+use std::path::Path;
+
+use crate::{
+    AssetError,
+    codegen::TEXT_SIZES,
+    gfx::{Indexed, MapType},
+    image::{COLOR_KEY, Image},
+    ldtk,
+    map::Tileset,
+    palette::{self, Depth, Palette},
+    tmx,
+};
+
+pub const MAGIC: &[u8; 4] = b"DHLV";
+pub const VERSION: u8 = 1;
+/// Text backgrounds a screen can show at once.
+const MAX_LAYERS: usize = 4;
+
+/// A level as drawn in the editor, before conversion.
+pub struct Level {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    /// Visible tile layers, topmost first.
+    pub layers: Vec<Image>,
+    pub collision: Option<Collision>,
+    pub entities: Vec<Entity>,
+}
+
+/// Which cells of the level are solid, and how.
+pub struct Collision {
+    pub cell_width: u32,
+    pub cell_height: u32,
+    pub cols: u32,
+    pub rows: u32,
+    /// One per cell, row by row. 0 is empty, anything else is up to the game.
+    pub cells: Vec<u8>,
+}
+
+/// An entity spawn point.
+pub struct Entity {
+    pub kind: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub properties: Vec<(String, Property)>,
+}
+
+pub enum Property {
+    Int(i32),
+    Float(f32),
+    Bool(bool),
+    String(String),
+}
+
+/// How a tile is drawn, from the editor's flip flags.
+#[derive(Clone, Copy, Default)]
+pub struct Flip {
+    pub h: bool,
+    pub v: bool,
+    /// Swaps x and y, before flipping. Only valid for square tiles.
+    pub diagonal: bool,
+}
+
+impl Level {
+    /// Opens the levels of a `.tmx` or `.ldtk` file, a Tiled map being a single level.
+    pub fn open(path: &Path) -> Result<Vec<Self>, AssetError> {
+        if path.extension().is_some_and(|ext| ext == "ldtk") {
+            ldtk::open(path)
+        } else {
+            tmx::open(path).map(|level| vec![level])
+        }
+    }
+
+    /// A level with no layers, for the editors' importers to fill.
+    pub fn new(name: String, width: u32, height: u32) -> Self {
+        Self {
+            name,
+            width,
+            height,
+            layers: vec![],
+            collision: None,
+            entities: vec![],
+        }
+    }
+
+    /// A fully transparent image the size of the level, to draw a layer on.
+    pub fn canvas(&self) -> Image {
+        let pixels = vec![[0; 4]; (self.width * self.height) as usize];
+        Image::from_rgba(self.width, self.height, pixels)
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, String> {
+        let Some(size) = TEXT_SIZES
+            .iter()
+            .position(|&(w, h)| self.width <= w && self.height <= h)
+        else {
+            return Err(format!(
+                "{}x{} is larger than the biggest text background, 512x512",
+                self.width, self.height
+            ));
+        };
+        if self.layers.len() > MAX_LAYERS {
+            return Err(format!(
+                "has {} tile layers, a screen only has {MAX_LAYERS} backgrounds",
+                self.layers.len()
+            ));
+        }
+
+        let mut palette = Palette::new(COLOR_KEY);
+        palette
+            .insert_all(&palette::color_set(&self.layers))
+            .map_err(|needed| {
+                format!(
+                    "needs {needed} colors, the limit is {}",
+                    Depth::Bpp8.max_colors()
+                )
+            })?;
+        let (width, height) = TEXT_SIZES[size];
+        let mut tileset = Tileset::new(MapType::Text);
+        let mut maps = Vec::with_capacity(self.layers.len());
+        for layer in &self.layers {
+            let padded = pad(layer, width, height);
+            maps.push(tileset.add(&Indexed::new(&padded, &palette, None))?);
+        }
+
+        let empty = Collision {
+            cell_width: 0,
+            cell_height: 0,
+            cols: 0,
+            rows: 0,
+            cells: vec![],
+        };
+        let collision = self.collision.as_ref().unwrap_or(&empty);
+        let entity_count = u16::try_from(self.entities.len()).map_err(|_| "too many entities")?;
+        let mut out = Vec::from(*MAGIC);
+        out.push(VERSION);
+        out.push(self.layers.len() as u8);
+        out.extend(entity_count.to_le_bytes());
+        // The level is at most 512x512 pixels, so neither its size nor its cell count overflow.
+        out.extend((self.width as u16).to_le_bytes());
+        out.extend((self.height as u16).to_le_bytes());
+        out.push(u8::try_from(collision.cell_width).map_err(|_| "collision cells are too wide")?);
+        out.push(u8::try_from(collision.cell_height).map_err(|_| "collision cells are too tall")?);
+        out.extend((collision.cols as u16).to_le_bytes());
+        out.extend((collision.rows as u16).to_le_bytes());
+        out.extend((tileset.len() as u16).to_le_bytes());
+        out.extend(palette.to_bytes());
+        out.extend(tileset.to_bytes(Depth::Bpp8));
+        for map in &maps {
+            out.extend([size as u8, 0, 0, 0]);
+            out.extend(map.to_bytes(0));
+        }
+        out.extend(&collision.cells);
+        out.resize(out.len().next_multiple_of(4), 0);
+        for entity in &self.entities {
+            entity.encode(&mut out)?;
+        }
+        Ok(out)
+    }
+}
+
+impl Entity {
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), String> {
+        let err = |what: &str| format!("entity `{}` has {what}", self.kind);
+        push_str(out, &self.kind)?;
+        for pos in [self.x, self.y] {
+            let pos = i16::try_from(pos).map_err(|_| err("an out of range position"))?;
+            out.extend(pos.to_le_bytes());
+        }
+        for size in [self.width, self.height] {
+            let size = u16::try_from(size).map_err(|_| err("an out of range size"))?;
+            out.extend(size.to_le_bytes());
+        }
+        out.push(u8::try_from(self.properties.len()).map_err(|_| err("too many properties"))?);
+        for (name, property) in &self.properties {
+            push_str(out, name)?;
+            match property {
+                Property::Int(value) => {
+                    out.push(0);
+                    out.extend(value.to_le_bytes());
+                }
+                Property::Float(value) => {
+                    out.push(1);
+                    out.extend(value.to_le_bytes());
+                }
+                Property::Bool(value) => out.extend([2, *value as u8]),
+                Property::String(value) => {
+                    out.push(3);
+                    push_str(out, value)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Draws the `width`x`height` tile of `src` at `(src_x, src_y)` over `dst` at `(x, y)`.
+///
+/// Transparent pixels are skipped, and whatever falls outside of `dst` is clipped.
+pub fn draw_tile(
+    dst: &mut Image,
+    (x, y): (i32, i32),
+    src: &Image,
+    (src_x, src_y): (u32, u32),
+    (width, height): (u32, u32),
+    flip: Flip,
+) {
+    for v in 0..height {
+        for u in 0..width {
+            let (dx, dy) = (x + u as i32, y + v as i32);
+            if dx < 0 || dy < 0 || dx as u32 >= dst.width || dy as u32 >= dst.height {
+                continue;
+            }
+            let fu = if flip.h { width - 1 - u } else { u };
+            let fv = if flip.v { height - 1 - v } else { v };
+            let (su, sv) = if flip.diagonal { (fv, fu) } else { (fu, fv) };
+            let (sx, sy) = (src_x + su, src_y + sv);
+            if sx >= src.width || sy >= src.height {
+                continue;
+            }
+            let px = src.pixels[(sy * src.width + sx) as usize];
+            if !Image::is_transparent(px) {
+                dst.pixels[(dy as u32 * dst.width + dx as u32) as usize] = px;
+            }
+        }
+    }
+}
+
+/// `image` in the top left corner of a transparent `width`x`height` image.
+fn pad(image: &Image, width: u32, height: u32) -> Image {
+    let mut pixels = vec![[0; 4]; (width * height) as usize];
+    for (y, row) in image.pixels.chunks_exact(image.width as usize).enumerate() {
+        let start = y * width as usize;
+        pixels[start..start + row.len()].copy_from_slice(row);
+    }
+    Image::from_rgba(width, height, pixels)
+}
+
+fn push_str(out: &mut Vec<u8>, s: &str) -> Result<(), String> {
+    let len = u8::try_from(s.len()).map_err(|_| format!("`{s}` is longer than 255 bytes"))?;
+    out.push(len);
+    out.extend(s.as_bytes());
+    Ok(())
+}
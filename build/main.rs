@@ -0,0 +1,758 @@
+// `tools/build-tests` compiles this as a test, which only runs the modules' tests.
+#![cfg_attr(test, allow(dead_code))]
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+mod banner;
+mod cache;
+mod codegen;
+mod collision;
+mod compress;
+mod font;
+mod gfx;
+mod image;
+mod ldtk;
+mod level;
+mod manifest;
+mod map;
+mod pack;
+mod palette;
+mod prefab;
+mod pxo;
+mod sound;
+mod tmx;
+
+use banner::BannerOptions;
+use cache::{Cache, Output};
+use codegen::{AssetInfo, CollisionInfo, FontInfo, GroupInfo, LevelInfo, MapInfo, SoundInfo};
+use compress::Compression;
+use font::Font;
+use gfx::{Format, Indexed, MapType};
+use image::{COLOR_KEY, Image};
+use level::Level;
+use manifest::Manifest;
+use map::Tileset;
+use pack::Pack;
+use palette::{BankError, Depth, Palette};
+use prefab::Prefab;
+use pxo::Project;
+use sound::{Bank, Sound, SoundFormat};
+
+/// Name of the asset pack in `romfs/`.
+const PACK_FILE: &str = "assets.pak";
+/// Path of the sound bank in the asset pack.
+const SOUND_BANK: &str = "sounds.bank";
+/// The ROM banner, which `[package.metadata.nds]` points the ROM packager at.
+const BANNER_FILE: &str = "banner.bin";
+
+/// An error tied to the asset that caused it.
+pub struct AssetError {
+    path: PathBuf,
+    msg: String,
+}
+
+impl AssetError {
+    pub fn new(path: &Path, msg: impl Into<String>) -> Self {
+        Self {
+            path: path.to_owned(),
+            msg: msg.into(),
+        }
+    }
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.msg)
+    }
+}
+
+impl fmt::Debug for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for AssetError {}
+
+type Result<T> = std::result::Result<T, AssetError>;
+
+/// Lists the files in `data_path` with one of `extensions`, sorted by name.
+fn list_files(data_path: &Path, extensions: &[&str]) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    if !data_path.exists() {
+        return Ok(files);
+    }
+    let entries = data_path
+        .read_dir()
+        .map_err(|e| AssetError::new(data_path, e.to_string()))?;
+    for entry in entries {
+        let path = entry
+            .map_err(|e| AssetError::new(data_path, e.to_string()))?
+            .path();
+        if path
+            .extension()
+            .is_some_and(|ext| extensions.iter().any(|e| ext == *e))
+        {
+            cache::track(&path);
+            files.push(path);
+        }
+    }
+    // Keeps palette order stable between builds.
+    files.sort();
+    Ok(files)
+}
+
+/// Lists the `.png` and `.pxo` files in `data_path`, less those the manifest ignores.
+///
+/// Of a `.png` and a `.pxo` with the same stem, the PNG is usually an export of the project, so
+/// the `.pxo` is converted and the PNG must be the same size. When they don't match, which one
+/// is stale is up to the manifest, by ignoring one of them.
+fn list_sources(manifest: &Manifest, data_path: &Path) -> Result<Vec<PathBuf>> {
+    let mut sources = list_files(data_path, &["png", "pxo"])?;
+    sources.retain(|path| !manifest.options(path).ignore);
+    let pairs: Vec<(PathBuf, PathBuf)> = sources
+        .iter()
+        .filter(|path| path.extension().is_some_and(|ext| ext == "pxo"))
+        .map(|pxo| (pxo.with_extension("png"), pxo.clone()))
+        .filter(|(png, _)| sources.contains(png))
+        .collect();
+    for (png, pxo) in pairs {
+        let (project, image) = (Project::open(&pxo)?, Image::open(&png)?);
+        if (project.width, project.height) != (image.width, image.height) {
+            return Err(AssetError::new(
+                &pxo,
+                format!(
+                    "is {}x{} but {} is {}x{}. Export the project again, or set `ignore = true` \
+                     in the manifest for whichever is stale",
+                    project.width,
+                    project.height,
+                    png.display(),
+                    image.width,
+                    image.height
+                ),
+            ));
+        }
+        sources.retain(|path| *path != png);
+    }
+    Ok(sources)
+}
+
+fn open_source(path: &Path) -> Result<Project> {
+    if path.extension().is_some_and(|ext| ext == "pxo") {
+        Project::open(path)
+    } else {
+        Image::open(path).map(Project::from)
+    }
+}
+
+/// The depth an asset of `format` is converted to, from its `bpp` option.
+fn asset_depth(format: Format, bpp: Option<u8>) -> std::result::Result<Depth, String> {
+    match (format, bpp) {
+        (Format::Bitmap16, Some(_)) => Err("16bpp bitmaps have no `bpp` option".into()),
+        (_, None | Some(8)) => Ok(Depth::Bpp8),
+        (Format::Tiled | Format::Map(MapType::Text), Some(4)) => Ok(Depth::Bpp4),
+        (_, Some(4)) => Err("only sprites and text backgrounds can be 4bpp".into()),
+        (_, Some(bpp)) => Err(format!("`bpp` is 4 or 8, not {bpp}")),
+    }
+}
+
+/// A directory of images converted together into one output directory, sharing a palette.
+struct Group<'a> {
+    /// Module of the generated `assets` file the group's constants go in.
+    module: Option<&'a str>,
+    data_path: &'a Path,
+    /// Directory of the group's files in the asset pack.
+    pack_dir: &'a str,
+    format: Format,
+    /// Whether assets may have more than one frame.
+    animated: bool,
+}
+
+/// Fails if two of `sources` would get the same static in the generated `assets` module.
+fn check_const_names(sources: &[PathBuf]) -> Result<()> {
+    match codegen::const_name_clash(sources) {
+        Some((first, second)) => Err(AssetError::new(
+            second,
+            format!(
+                "would be `{}` like {}, rename one of them",
+                codegen::const_name(&second.file_stem().unwrap().to_string_lossy()),
+                first.display()
+            ),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Adds the files of a conversion of `source` to the pack.
+fn add_output<T>(pack: &mut Pack, source: &Path, output: Output<T>) -> Result<T> {
+    for (path, data) in output.files {
+        pack.add(path, data)
+            .map_err(|e| AssetError::new(source, e))?;
+    }
+    Ok(output.info)
+}
+
+/// Converts the group unless it is cached, the whole group being converted again when any of
+/// its images changes since they share a palette.
+fn convert_group(
+    cache: &mut Cache,
+    pack: &mut Pack,
+    manifest: &Manifest,
+    group: &Group,
+) -> Result<GroupInfo> {
+    let sources = list_sources(manifest, group.data_path)?;
+    check_const_names(&sources)?;
+    let mut key = cache
+        .key()
+        .value((group.module, group.pack_dir, group.format, group.animated));
+    for source in &sources {
+        key = key.file(source)?.value(manifest.options(source));
+        // Tracked here rather than by the conversion, which only runs for existing sidecars.
+        let sidecar = collision::sidecar_path(source);
+        let has_sidecar = sidecar.exists();
+        key = key.value(has_sidecar);
+        if has_sidecar {
+            cache::track(&sidecar);
+            key = key.file(&sidecar)?;
+        }
+    }
+    let output = cache.convert(key, || convert_images(manifest, group, &sources))?;
+    add_output(pack, group.data_path, output)
+}
+
+/// Converts every image in the group to `<stem>.img.bin`, sharing a single `pal.bin` unless
+/// the group is direct color.
+///
+/// Frames of animated images are stored one after the other, and sprites get the collision
+/// masks of their frames in `<stem>.mask.bin`.
+fn convert_images(
+    manifest: &Manifest,
+    group: &Group,
+    sources: &[PathBuf],
+) -> Result<Output<GroupInfo>> {
+    let pack_path = |file: &str| format!("{}{file}", group.pack_dir);
+
+    let mut projects = Vec::with_capacity(sources.len());
+    let mut kinds = Vec::with_capacity(sources.len());
+    let mut depths = Vec::with_capacity(sources.len());
+    for source in sources {
+        let mut project = open_source(source)?;
+        if !group.animated && project.frames.len() != 1 {
+            return Err(AssetError::new(
+                source,
+                "only sprites can have more than one frame",
+            ));
+        }
+        let depth = asset_depth(group.format, manifest.options(source).bpp)
+            .map_err(|e| AssetError::new(source, e))?;
+        // Checked before anything else, so a wrong size isn't reported as some other problem.
+        let kind = codegen::asset_kind(group.format, depth, project.width, project.height)
+            .map_err(|e| AssetError::new(source, e))?;
+        if group.format != Format::Bitmap16 && manifest.options(source).dither {
+            return Err(AssetError::new(
+                source,
+                "only 16bpp bitmaps are dithered, paletted images keep their exact colors",
+            ));
+        }
+        let sidecar = collision::sidecar_path(source);
+        if sidecar.exists() {
+            let hitboxes = collision::read_sidecar(
+                &sidecar,
+                project.frames.len(),
+                project.width,
+                project.height,
+            )?;
+            for hitbox in hitboxes {
+                if project.hitboxes.iter().any(|h| h.name == hitbox.name) {
+                    return Err(AssetError::new(
+                        &sidecar,
+                        format!("`{}` is also a layer of the image", hitbox.name),
+                    ));
+                }
+                project.hitboxes.push(hitbox);
+            }
+        }
+        if group.format != Format::Tiled && !project.hitboxes.is_empty() {
+            return Err(AssetError::new(source, "only sprites can have hitboxes"));
+        }
+        projects.push(project);
+        kinds.push(kind);
+        depths.push(depth);
+    }
+
+    if group.format == Format::Bitmap16 {
+        let mut assets = vec![];
+        let mut files = vec![];
+        for ((source, project), kind) in sources.iter().zip(projects).zip(kinds) {
+            let options = manifest.options(source);
+            let data = gfx::direct_color(&project.frames[0].image, options.dither);
+            let stem = source.file_stem().unwrap().to_string_lossy().into_owned();
+            let path = pack_path(&format!("{stem}.img.bin"));
+            let data = compress::encode(&data, options.compression)
+                .map_err(|e| AssetError::new(source, e))?;
+            files.push((path.clone(), data));
+            assets.push(AssetInfo {
+                pack_path: path,
+                stem,
+                width: project.width,
+                height: project.height,
+                kind,
+                depth: Depth::Bpp8,
+                palette_bank: 0,
+                frame_durations: project.frames.iter().map(|f| f.duration_ms).collect(),
+                tags: project.tags,
+                map: None,
+                collision: None,
+            });
+        }
+        return Ok(Output {
+            info: GroupInfo {
+                module: group.module.map(str::to_owned),
+                palette_path: None,
+                palette_colors: 0,
+                assets,
+            },
+            files,
+        });
+    }
+
+    // Every color is known before encoding, so the whole group can be planned at once: 4bpp
+    // assets are packed into banks first, then 8bpp ones share the whole palette with them.
+    let sets: Vec<_> = projects
+        .iter()
+        .map(|p| palette::color_set(p.frames.iter().map(|f| &f.image)))
+        .collect();
+    let bpp4: Vec<usize> = (0..sources.len())
+        .filter(|&i| depths[i] == Depth::Bpp4)
+        .collect();
+    let bank_sets: Vec<_> = bpp4.iter().map(|&i| sets[i].clone()).collect();
+    let (mut palette, packed) =
+        Palette::pack_banks(COLOR_KEY, &bank_sets).map_err(|e| match e {
+            BankError::TooManyColors { asset, colors } => AssetError::new(
+                &sources[bpp4[asset]],
+                format!(
+                    "uses {colors} colors, a 4bpp palette bank only fits {}",
+                    Depth::Bpp4.max_colors()
+                ),
+            ),
+            BankError::OutOfBanks { asset } => AssetError::new(
+                &sources[bpp4[asset]],
+                format!(
+                    "no palette bank left, the group needs more than {}",
+                    Palette::BANKS
+                ),
+            ),
+        })?;
+    let mut banks = vec![None; sources.len()];
+    for (&i, bank) in bpp4.iter().zip(packed) {
+        banks[i] = Some(bank);
+    }
+    for ((source, set), depth) in sources.iter().zip(&sets).zip(&depths) {
+        if *depth != Depth::Bpp8 {
+            continue;
+        }
+        palette.insert_all(set).map_err(|needed| {
+            AssetError::new(
+                source,
+                format!(
+                    "the group's palette would need {needed} colors, the limit is {}",
+                    Depth::Bpp8.max_colors()
+                ),
+            )
+        })?;
+    }
+
+    let mut assets = vec![];
+    let mut files = vec![];
+    for (i, ((source, project), kind)) in sources.iter().zip(projects).zip(kinds).enumerate() {
+        let err = |msg: String| AssetError::new(source, msg);
+        let compression = manifest.options(source).compression;
+        let mut add_asset = |path: String, data: &[u8]| {
+            compress::encode(data, compression)
+                .map(|data| files.push((path, data)))
+                .map_err(err)
+        };
+        let (bank, depth) = (banks[i], depths[i]);
+        let stem = source.file_stem().unwrap().to_string_lossy().into_owned();
+        let mut data = vec![];
+        let mut map = None;
+        let mut collision = None;
+        if let Format::Map(map_type) = group.format {
+            let indexed = Indexed::new(&project.frames[0].image, &palette, bank);
+            let mut tileset = Tileset::new(map_type);
+            let tile_map = tileset.add(&indexed).map_err(err)?;
+            let map_path = pack_path(&format!("{stem}.map.bin"));
+            add_asset(map_path.clone(), &tile_map.to_bytes(bank.unwrap_or(0)))?;
+            data = tileset.to_bytes(depth);
+            map = Some(MapInfo {
+                pack_path: map_path,
+                tile_count: tileset.len(),
+            });
+        } else {
+            for frame in &project.frames {
+                let indexed = Indexed::new(&frame.image, &palette, bank);
+                data.extend(indexed.encode(group.format, depth).map_err(err)?);
+            }
+        }
+        if group.format == Format::Tiled {
+            let mask_path = pack_path(&format!("{stem}.mask.bin"));
+            let masks: Vec<u8> = (project.frames.iter())
+                .flat_map(|f| collision::mask(&f.image))
+                .collect();
+            add_asset(mask_path.clone(), &masks)?;
+            collision = Some(CollisionInfo {
+                mask_path,
+                bounds: (project.frames.iter())
+                    .map(|f| collision::Rect::bounds(&f.image))
+                    .collect(),
+                hitboxes: project.hitboxes,
+            });
+        }
+        let path = pack_path(&format!("{stem}.img.bin"));
+        add_asset(path.clone(), &data)?;
+        assets.push(AssetInfo {
+            pack_path: path,
+            stem,
+            width: project.width,
+            height: project.height,
+            kind,
+            depth,
+            palette_bank: bank.unwrap_or(0),
+            frame_durations: project.frames.iter().map(|f| f.duration_ms).collect(),
+            tags: project.tags,
+            map,
+            collision,
+        });
+    }
+    files.push((pack_path("pal.bin"), palette.to_bytes()));
+    Ok(Output {
+        info: GroupInfo {
+            module: group.module.map(str::to_owned),
+            palette_path: Some(pack_path("pal.bin")),
+            palette_colors: palette.len(),
+            assets,
+        },
+        files,
+    })
+}
+
+/// Converts every Tiled map and LDtk level in `data_path` to `levels/<name>.lvl` in the pack.
+fn convert_levels(
+    cache: &mut Cache,
+    pack: &mut Pack,
+    manifest: &Manifest,
+    data_path: &Path,
+) -> Result<Vec<LevelInfo>> {
+    let sources = list_files(data_path, &["tmx", "ldtk"])?;
+    let mut levels: Vec<LevelInfo> = vec![];
+    for source in &sources {
+        let options = manifest.options(source);
+        let key = cache.key().file(source)?.value(options);
+        let output = cache.convert(key, || {
+            let mut output = Output {
+                info: vec![],
+                files: vec![],
+            };
+            for level in Level::open(source)? {
+                let path = format!("levels/{}.lvl", level.name);
+                let data = level
+                    .encode()
+                    .and_then(|data| compress::encode(&data, options.compression))
+                    .map_err(|e| AssetError::new(source, format!("level `{}` {e}", level.name)))?;
+                output.files.push((path.clone(), data));
+                output.info.push(LevelInfo {
+                    pack_path: path,
+                    name: level.name,
+                    width: level.width,
+                    height: level.height,
+                });
+            }
+            Ok(output)
+        })?;
+        for level in add_output(pack, source, output)? {
+            // Names differing only in case or separators still clash as constants.
+            let name = codegen::const_name(&level.name);
+            if let Some(other) = levels.iter().find(|l| codegen::const_name(&l.name) == name) {
+                return Err(AssetError::new(
+                    source,
+                    format!(
+                        "level `{}` would be `levels::{name}` like level `{}`, rename one of them",
+                        level.name, other.name
+                    ),
+                ));
+            }
+            levels.push(level);
+        }
+    }
+    Ok(levels)
+}
+
+/// Converts every BDF, TrueType and OpenType font in `data_path` to `fonts/<name>.fnt` in the pack.
+fn convert_fonts(
+    cache: &mut Cache,
+    pack: &mut Pack,
+    manifest: &Manifest,
+    data_path: &Path,
+) -> Result<Vec<FontInfo>> {
+    let sources = list_files(data_path, &["bdf", "ttf", "otf"])?;
+    check_const_names(&sources)?;
+    let mut fonts = vec![];
+    for source in &sources {
+        let options = manifest.options(source);
+        let key = cache.key().file(source)?.value(options);
+        let output = cache.convert(key, || {
+            let font = Font::open(source, &options)?;
+            let path = format!("fonts/{}.fnt", font.name);
+            let data = font
+                .encode()
+                .and_then(|data| compress::encode(&data, options.compression))
+                .map_err(|e| AssetError::new(source, e))?;
+            Ok(Output {
+                info: FontInfo {
+                    pack_path: path.clone(),
+                    line_height: font.line_height(),
+                    glyphs: font.glyphs.len(),
+                    name: font.name,
+                },
+                files: vec![(path, data)],
+            })
+        })?;
+        fonts.push(add_output(pack, source, output)?);
+    }
+    Ok(fonts)
+}
+
+/// Converts the WAVs in `data/sfx` and `data/music` into the sound bank, sound effects playing
+/// once and music looping by default.
+fn convert_sounds(
+    cache: &mut Cache,
+    pack: &mut Pack,
+    manifest: &Manifest,
+    data_path: &Path,
+) -> Result<Vec<SoundInfo>> {
+    let dirs = [
+        ("sfx", SoundFormat::Pcm8, false),
+        ("music", SoundFormat::Adpcm, true),
+    ];
+    let mut bank = Bank::default();
+    let mut sounds: Vec<SoundInfo> = vec![];
+    for (dir, default_format, looped) in dirs {
+        for source in list_files(&data_path.join(dir), &["wav"])? {
+            let err = |msg: String| AssetError::new(&source, msg);
+            let options = manifest.options(&source);
+            if options.compression != Compression::None {
+                return Err(err(
+                    "sounds can't be compressed, use a `sound_format` of \"adpcm\" instead".into(),
+                ));
+            }
+            let format = options.sound_format.unwrap_or(default_format);
+            let key = cache.key().file(&source)?.value((options, format, looped));
+            let mut output = cache.convert(key, || {
+                let sound = Sound::open(&source, &options, looped)?;
+                let (data, loop_word) = sound.encode(format).map_err(err)?;
+                Ok(Output {
+                    info: SoundInfo {
+                        source: format!("{dir}/{}", source.file_name().unwrap().to_string_lossy()),
+                        format,
+                        rate: sound.rate,
+                        duration_secs: sound.duration_secs(),
+                        loop_word,
+                        name: sound.name,
+                    },
+                    files: vec![(String::new(), data)],
+                })
+            })?;
+            // Variants are named after the stem, whichever directory the sound is in.
+            let name = codegen::type_name(&output.info.name);
+            if let Some(other) = sounds.iter().find(|s| codegen::type_name(&s.name) == name) {
+                return Err(err(format!(
+                    "would be `Sound::{name}` like `{}`, rename one of them",
+                    other.source
+                )));
+            }
+            let (_, data) = output.files.remove(0);
+            bank.add(&output.info, data);
+            sounds.push(output.info);
+        }
+    }
+    if sounds.len() > u16::MAX as usize {
+        return Err(AssetError::new(
+            data_path,
+            format!("{} sounds is too many for a bank", sounds.len()),
+        ));
+    }
+    pack.add(SOUND_BANK.into(), bank.to_bytes())
+        .map_err(|e| AssetError::new(data_path, e))?;
+    Ok(sounds)
+}
+
+/// Reads every prefab in `data_path`, whose sprites are the assets of `sprites`.
+fn read_prefabs(data_path: &Path, sprites: &GroupInfo) -> Result<Vec<Prefab>> {
+    let stems: Vec<&str> = sprites.assets.iter().map(|a| a.stem.as_str()).collect();
+    list_files(data_path, &["toml"])?
+        .iter()
+        .map(|path| Prefab::open(path, &stems))
+        .collect()
+}
+
+/// Builds the ROM banner from `icon.png` or `icon.pxo` in `data_path` and the `[banner]` table
+/// of the manifest, titled after the package if there is none.
+///
+/// Without an icon there is no banner, and the packager uses its default one.
+fn convert_banner(manifest: &Manifest, data_path: &Path) -> Result<Option<Vec<u8>>> {
+    let icon = list_sources(manifest, data_path)?
+        .into_iter()
+        .find(|path| path.file_stem().is_some_and(|stem| stem == "icon"));
+    let Some(icon) = icon else {
+        return match manifest.banner {
+            Some(_) => Err(AssetError::new(
+                data_path,
+                "has no icon.png nor icon.pxo for the `[banner]` of the manifest",
+            )),
+            None => Ok(None),
+        };
+    };
+    let options = manifest.banner.clone().unwrap_or_else(|| BannerOptions {
+        title: env!("CARGO_PKG_NAME").into(),
+        subtitle: None,
+        author: None,
+        japanese: None,
+        french: None,
+        german: None,
+        italian: None,
+        spanish: None,
+        chinese: None,
+        korean: None,
+    });
+    let project = open_source(&icon)?;
+    banner::build(&project, &options)
+        .map(Some)
+        .map_err(|e| AssetError::new(&icon, e))
+}
+
+/// Writes `data` to `path` unless it is already there, so what depends on the file isn't rebuilt
+/// for nothing.
+fn write(path: &Path, data: &[u8]) -> Result<()> {
+    if std::fs::read(path).is_ok_and(|old| old == data) {
+        return Ok(());
+    }
+    std::fs::write(path, data).map_err(|e| AssetError::new(path, e.to_string()))
+}
+
+fn main() -> Result<()> {
+    let out = Path::new("romfs");
+    let data_path = Path::new("data/");
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    // Everything in romfs/ is generated, clearing it drops files of assets that no longer exist.
+    // The pack is kept to only be rewritten if it changed.
+    let err = |e: std::io::Error| AssetError::new(out, e.to_string());
+    std::fs::create_dir_all(out).map_err(err)?;
+    for entry in out.read_dir().map_err(err)? {
+        let path = entry.map_err(err)?.path();
+        if path.file_name().is_some_and(|name| name == PACK_FILE) {
+            continue;
+        }
+        if path.is_dir() {
+            std::fs::remove_dir_all(&path).map_err(err)?;
+        } else {
+            std::fs::remove_file(&path).map_err(err)?;
+        }
+    }
+    // Catches files added anywhere in data/, even in directories that don't exist yet.
+    cache::track(data_path);
+    let manifest = Manifest::open(data_path)?;
+    let mut cache = Cache::open(out_dir.join("asset-cache"))?;
+    let sprites = Group {
+        module: None,
+        data_path,
+        pack_dir: "",
+        format: Format::Tiled,
+        animated: true,
+    };
+    let bgs = Group {
+        module: Some("bg"),
+        data_path: &data_path.join("bg"),
+        pack_dir: "bg/",
+        format: Format::Bitmap,
+        animated: false,
+    };
+    let direct_bgs = Group {
+        module: Some("bg16"),
+        data_path: &data_path.join("bg16"),
+        pack_dir: "bg16/",
+        format: Format::Bitmap16,
+        ..bgs
+    };
+    let maps = Group {
+        module: Some("maps"),
+        data_path: &data_path.join("maps"),
+        pack_dir: "maps/",
+        format: Format::Map(MapType::Text),
+        animated: false,
+    };
+    let rotation_maps = Group {
+        module: Some("rotation_maps"),
+        data_path: &data_path.join("maps/rotation"),
+        pack_dir: "maps/rotation/",
+        format: Format::Map(MapType::Rotation),
+        ..maps
+    };
+    let ex_rotation_maps = Group {
+        module: Some("ex_rotation_maps"),
+        data_path: &data_path.join("maps/ex_rotation"),
+        pack_dir: "maps/ex_rotation/",
+        format: Format::Map(MapType::ExRotation),
+        ..maps
+    };
+    let mut pack = Pack::new();
+    let groups = [
+        convert_group(&mut cache, &mut pack, &manifest, &sprites)?,
+        convert_group(&mut cache, &mut pack, &manifest, &bgs)?,
+        convert_group(&mut cache, &mut pack, &manifest, &direct_bgs)?,
+        convert_group(&mut cache, &mut pack, &manifest, &maps)?,
+        convert_group(&mut cache, &mut pack, &manifest, &rotation_maps)?,
+        convert_group(&mut cache, &mut pack, &manifest, &ex_rotation_maps)?,
+    ];
+    let levels = convert_levels(&mut cache, &mut pack, &manifest, &data_path.join("levels"))?;
+    let fonts = convert_fonts(&mut cache, &mut pack, &manifest, &data_path.join("fonts"))?;
+    let sounds = convert_sounds(&mut cache, &mut pack, &manifest, data_path)?;
+    let prefabs = read_prefabs(&data_path.join("prefabs"), &groups[0])?;
+
+    let pack_path = out.join(PACK_FILE);
+    let err = |msg: String| AssetError::new(&pack_path, msg);
+    let bytes = pack.to_bytes().map_err(err)?;
+    // Reading it back catches a broken writer before the game does.
+    pack::parse(&bytes).map_err(|e| err(format!("does not read back: {e}")))?;
+    write(&pack_path, &bytes)?;
+    cache.finish()?;
+
+    let banner_path = Path::new(BANNER_FILE);
+    match convert_banner(&manifest, &data_path.join("banner"))? {
+        Some(banner) => write(banner_path, &banner)?,
+        None if banner_path.exists() => std::fs::remove_file(banner_path)
+            .map_err(|e| AssetError::new(banner_path, e.to_string()))?,
+        None => {}
+    }
+
+    write(
+        &out_dir.join("assets.rs"),
+        codegen::generate(
+            &format!("nitro:/{PACK_FILE}"),
+            &groups,
+            &levels,
+            &fonts,
+            SOUND_BANK,
+            &sounds,
+            &prefabs,
+        )
+        .as_bytes(),
+    )?;
+
+    let out = out.canonicalize().unwrap();
+    let out = out.as_path();
+    println!("cargo::rustc-env=BUILD_DIR={}", out.display());
+    Ok(())
+}
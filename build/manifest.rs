@@ -0,0 +1,112 @@
+//! `data/assets.toml`, build options of individual assets.
+//!
+//! Each table is named after the path of an asset relative to `data/`, e.g.:
+//!
+//! ```toml
+//! ["bg/bg.png"]
+//! compression = "lz77"
+//! ```
+//!
+//! Assets without a table use the defaults.
+//!
+//! The `[banner]` table holds the titles of the ROM banner, see [`BannerOptions`].
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::{AssetError, banner::BannerOptions, compress::Compression, sound::SoundFormat};
+
+pub const FILE_NAME: &str = "assets.toml";
+
+#[derive(Clone, Copy, Default, Hash, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AssetOptions {
+    /// How every file converted from the asset is compressed, `"none"`, `"lz77"` or `"rle"`.
+    #[serde(default)]
+    pub compression: Compression,
+    /// Pixel size TrueType and OpenType fonts are rasterized at, which they need.
+    pub font_size: Option<u32>,
+    /// Bits per pixel of a font's glyphs, 1 or 4 for anti-aliasing. Defaults to 1 for BDF fonts
+    /// and 4 for the others.
+    pub font_bpp: Option<u8>,
+    /// Bits per pixel of a sprite or text background, 8 to use the group's whole palette or 4 to
+    /// use one of its 16 color banks, shared with other 4bpp assets where their colors fit.
+    /// Defaults to 8.
+    pub bpp: Option<u8>,
+    /// Whether an image is left out of the build, for a `.png` and a `.pxo` of the same name
+    /// that don't match.
+    #[serde(default)]
+    pub ignore: bool,
+    /// Whether a 16bpp bitmap is dithered down from 24 bit color rather than truncated.
+    #[serde(default)]
+    pub dither: bool,
+    /// Sample format of a sound, `"pcm8"`, `"pcm16"` or `"adpcm"`. Defaults to PCM8 for sound
+    /// effects and ADPCM for music.
+    pub sound_format: Option<SoundFormat>,
+    /// Sample a sound loops back to once it ends, overriding the loop of its `smpl` chunk. Music
+    /// loops from the start by default.
+    pub loop_start: Option<u32>,
+}
+
+/// The manifest as written.
+#[derive(Default, Deserialize)]
+struct File {
+    banner: Option<BannerOptions>,
+    #[serde(flatten)]
+    assets: BTreeMap<String, AssetOptions>,
+}
+
+pub struct Manifest {
+    data_path: PathBuf,
+    assets: BTreeMap<String, AssetOptions>,
+    pub banner: Option<BannerOptions>,
+}
+
+impl Manifest {
+    /// Reads the manifest in `data_path`, an empty one if there is none.
+    pub fn open(data_path: &Path) -> Result<Self, AssetError> {
+        let path = data_path.join(FILE_NAME);
+        // Cargo would run the build script every time for a file that doesn't exist, creating one
+        // is caught by tracking `data/`.
+        if path.exists() {
+            crate::cache::track(&path);
+        }
+        let err = |msg: String| AssetError::new(&path, msg);
+        let File { banner, assets } = match std::fs::read_to_string(&path) {
+            Ok(text) => toml::from_str(&text).map_err(|e| err(format!("invalid manifest: {e}")))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => File::default(),
+            Err(e) => return Err(err(format!("could not open: {e}"))),
+        };
+        // A typo would otherwise silently leave the asset with the defaults.
+        for name in assets.keys() {
+            if !data_path.join(name).is_file() {
+                return Err(err(format!(
+                    "`{name}` is not a file in {}",
+                    data_path.display()
+                )));
+            }
+        }
+        Ok(Self {
+            data_path: data_path.to_owned(),
+            assets,
+            banner,
+        })
+    }
+
+    /// Options of the asset converted from `source`.
+    pub fn options(&self, source: &Path) -> AssetOptions {
+        source
+            .strip_prefix(&self.data_path)
+            .ok()
+            .and_then(|name| {
+                // Tables use forward slashes whatever the host is.
+                let name = name.to_string_lossy().replace('\\', "/");
+                self.assets.get(&name).copied()
+            })
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,257 @@
+//! Tile deduplication and map generation for tiled backgrounds.
+
+use std::collections::HashMap;
+
+use crate::{
+    gfx::{self, Indexed, MapType, TILE_SIZE},
+    palette::Depth,
+};
+
+const TILE_PIXELS: usize = TILE_SIZE * TILE_SIZE;
+type Tile = [u8; TILE_PIXELS];
+
+/// Width and height of a text background screen block, in tiles.
+const SCREEN_BLOCK: usize = 32;
+
+#[derive(Clone, Copy)]
+struct Entry {
+    tile: u16,
+    h_flip: bool,
+    v_flip: bool,
+}
+
+/// Unique tiles shared by one or more maps, with lookups for their flipped versions.
+pub struct Tileset {
+    map_type: MapType,
+    tiles: Vec<Tile>,
+    known: HashMap<Tile, (u16, bool, bool)>,
+}
+
+impl Tileset {
+    /// Tile 0 is always fully transparent, so a cleared map shows nothing.
+    pub fn new(map_type: MapType) -> Self {
+        Self {
+            map_type,
+            tiles: vec![[0; TILE_PIXELS]],
+            known: HashMap::from([([0; TILE_PIXELS], (0, false, false))]),
+        }
+    }
+
+    /// Splits `image` into tiles, reusing identical ones, flipped if the map type allows it.
+    pub fn add(&mut self, image: &Indexed) -> Result<TileMap, String> {
+        let data = image.tiles()?;
+        let max_tiles = match self.map_type {
+            MapType::Rotation => 256,
+            MapType::Text | MapType::ExRotation => 1024,
+        };
+        let flips = self.map_type != MapType::Rotation;
+
+        let mut entries = Vec::with_capacity(data.len() / TILE_PIXELS);
+        for chunk in data.chunks_exact(TILE_PIXELS) {
+            let tile: Tile = chunk.try_into().unwrap();
+            let (index, h_flip, v_flip) = match self.known.get(&tile) {
+                Some(&found) => found,
+                None => {
+                    if self.tiles.len() == max_tiles {
+                        return Err(format!(
+                            "needs more than the {max_tiles} unique tiles a {:?} map can use",
+                            self.map_type
+                        ));
+                    }
+                    let index = self.tiles.len() as u16;
+                    self.tiles.push(tile);
+                    self.known.insert(tile, (index, false, false));
+                    if flips {
+                        let h = flip_h(&tile);
+                        self.known.entry(h).or_insert((index, true, false));
+                        self.known
+                            .entry(flip_v(&tile))
+                            .or_insert((index, false, true));
+                        self.known.entry(flip_v(&h)).or_insert((index, true, true));
+                    }
+                    (index, false, false)
+                }
+            };
+            entries.push(Entry {
+                tile: index,
+                h_flip,
+                v_flip,
+            });
+        }
+
+        Ok(TileMap {
+            map_type: self.map_type,
+            width: image.width / TILE_SIZE,
+            height: image.height / TILE_SIZE,
+            entries,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    /// Tile data, for the background's tile base.
+    pub fn to_bytes(&self, depth: Depth) -> Vec<u8> {
+        gfx::pack(self.tiles.concat(), depth)
+    }
+}
+
+/// A map rebuilding an image from the tiles of a [`Tileset`].
+pub struct TileMap {
+    map_type: MapType,
+    /// Size in tiles.
+    width: usize,
+    height: usize,
+    /// One per tile of the image, row by row.
+    entries: Vec<Entry>,
+}
+
+impl TileMap {
+    /// Map data, for the background's map base. `bank` is the palette bank of 4bpp maps.
+    pub fn to_bytes(&self, bank: u8) -> Vec<u8> {
+        match self.map_type {
+            MapType::Rotation => self.entries.iter().map(|e| e.tile as u8).collect(),
+            MapType::ExRotation => self
+                .entries
+                .iter()
+                .flat_map(|e| e.encode(0).to_le_bytes())
+                .collect(),
+            MapType::Text => {
+                // Screen blocks are stored one after the other, each one row by row.
+                let mut out = Vec::with_capacity(self.entries.len() * 2);
+                for by in (0..self.height).step_by(SCREEN_BLOCK) {
+                    for bx in (0..self.width).step_by(SCREEN_BLOCK) {
+                        for y in by..(by + SCREEN_BLOCK).min(self.height) {
+                            for x in bx..(bx + SCREEN_BLOCK).min(self.width) {
+                                let entry = self.entries[y * self.width + x];
+                                out.extend(entry.encode(bank).to_le_bytes());
+                            }
+                        }
+                    }
+                }
+                out
+            }
+        }
+    }
+}
+
+impl Entry {
+    const fn encode(self, bank: u8) -> u16 {
+        self.tile | (self.h_flip as u16) << 10 | (self.v_flip as u16) << 11 | (bank as u16) << 12
+    }
+}
+
+fn flip_h(tile: &Tile) -> Tile {
+    let mut out = *tile;
+    for row in out.chunks_exact_mut(TILE_SIZE) {
+        row.reverse();
+    }
+    out
+}
+
+fn flip_v(tile: &Tile) -> Tile {
+    let mut out = [0; TILE_PIXELS];
+    for (dst, src) in out
+        .chunks_exact_mut(TILE_SIZE)
+        .zip(tile.chunks_exact(TILE_SIZE).rev())
+    {
+        dst.copy_from_slice(src);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An image of `tiles` side by side.
+    fn row_of(tiles: &[Tile]) -> Indexed {
+        let width = tiles.len() * TILE_SIZE;
+        let mut indices = vec![0; width * TILE_SIZE];
+        for (i, tile) in tiles.iter().enumerate() {
+            for (y, row) in tile.chunks_exact(TILE_SIZE).enumerate() {
+                let start = y * width + i * TILE_SIZE;
+                indices[start..start + TILE_SIZE].copy_from_slice(row);
+            }
+        }
+        Indexed {
+            width,
+            height: TILE_SIZE,
+            indices,
+        }
+    }
+
+    fn entries(map: &[u8]) -> Vec<u16> {
+        map.chunks_exact(2)
+            .map(|e| u16::from_le_bytes([e[0], e[1]]))
+            .collect()
+    }
+
+    #[test]
+    fn flipped_tiles_are_reused() {
+        // Every pixel differs, so the tile matches itself under no flip.
+        let tile: Tile = std::array::from_fn(|i| i as u8 + 1);
+        let (h, v) = (flip_h(&tile), flip_v(&tile));
+        let image = row_of(&[tile, h, v, flip_v(&h)]);
+
+        let mut tileset = Tileset::new(MapType::Text);
+        let map = tileset.add(&image).unwrap();
+        assert_eq!(tileset.len(), 2);
+        let (h_bit, v_bit) = (1 << 10, 1 << 11);
+        assert_eq!(
+            entries(&map.to_bytes(0)),
+            [1, 1 | h_bit, 1 | v_bit, 1 | h_bit | v_bit]
+        );
+    }
+
+    #[test]
+    fn rotation_maps_do_not_flip() {
+        let tile: Tile = std::array::from_fn(|i| i as u8 + 1);
+        let image = row_of(&[tile, flip_h(&tile)]);
+
+        let mut tileset = Tileset::new(MapType::Rotation);
+        let map = tileset.add(&image).unwrap();
+        assert_eq!(tileset.len(), 3);
+        assert_eq!(map.to_bytes(0), [1, 2]);
+    }
+
+    #[test]
+    fn large_text_maps_are_split_into_screen_blocks() {
+        const SIZE: usize = 2 * SCREEN_BLOCK;
+        // Tiles are filled with their screen block, but for one marking the second tile of the
+        // second block.
+        let fill = |x: usize, y: usize| match (x, y) {
+            (33, 0) => 9,
+            _ => (y / SCREEN_BLOCK * 2 + x / SCREEN_BLOCK) as u8 + 1,
+        };
+        let width = SIZE * TILE_SIZE;
+        let indices = (0..width * width)
+            .map(|i| fill(i % width / TILE_SIZE, i / width / TILE_SIZE))
+            .collect();
+        let image = Indexed {
+            width,
+            height: width,
+            indices,
+        };
+
+        let mut tileset = Tileset::new(MapType::Text);
+        let map = tileset.add(&image).unwrap();
+        let entries = entries(&map.to_bytes(0));
+        assert_eq!(entries.len(), SIZE * SIZE);
+        let mut expected = vec![];
+        for (bx, by) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            for y in 0..SCREEN_BLOCK {
+                for x in 0..SCREEN_BLOCK {
+                    expected.push(fill(bx * SCREEN_BLOCK + x, by * SCREEN_BLOCK + y));
+                }
+            }
+        }
+        let found: Vec<u8> = entries
+            .iter()
+            .map(|&e| tileset.tiles[e as usize][0])
+            .collect();
+        assert_eq!(found, expected);
+        assert_eq!(found[SCREEN_BLOCK * SCREEN_BLOCK + 1], 9);
+    }
+}
@@ -0,0 +1,256 @@
+//! The asset pack, every converted file in one nitroFS file so the game only opens one.
+//!
+//! All integers are little endian:
+//!
+//! | Offset | Size       | Contents                                      |
+//! |--------|------------|-----------------------------------------------|
+//! | 0      | 4          | [`MAGIC`]                                     |
+//! | 4      | 2          | [`VERSION`]                                   |
+//! | 6      | 2          | entry count                                   |
+//! | 8      | 4          | offset of the name table                      |
+//! | 12     | 4          | size of the whole pack                        |
+//! | 16     | 16 × count | table of contents                             |
+//!
+//! A table of contents entry is the asset's id, the offset and length of its payload, and the
+//! CRC-32 of the payload. Entries are sorted by id so the game can binary search them. The id is
+//! the FNV-1a hash of the asset's path in the pack, which the generated code hashes again at
+//! compile time, so the game never deals in paths.
+//!
+//! Payloads follow the table, each starting on a [`PAYLOAD_ALIGN`] boundary, and the name table
+//! comes last: the paths in table order, each a length byte then UTF-8. Only tools read it.
+//!
+//! `tools/pak` includes this file too, so it only depends on `std`.
+
+pub const MAGIC: &[u8; 4] = b"DHPK";
+pub const VERSION: u16 = 1;
+const HEADER_LEN: usize = 16;
+const ENTRY_LEN: usize = 16;
+/// Enough for DMA, and keeps payloads off each other's cache lines.
+pub const PAYLOAD_ALIGN: usize = 32;
+
+/// FNV-1a, mirrored by `resources::AssetId::new` in the wrapper.
+pub fn id(path: &str) -> u32 {
+    path.bytes().fold(0x811C_9DC5, |hash, b| {
+        (hash ^ b as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// CRC-32 as used by zip and PNG.
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |mut crc, &b| {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = crc >> 1 ^ 0xEDB8_8320 & (crc & 1).wrapping_neg();
+        }
+        crc
+    })
+}
+
+/// Files waiting to be written to a pack.
+#[derive(Default)]
+pub struct Pack {
+    files: Vec<(String, Vec<u8>)>,
+}
+
+/// A table of contents entry, as read back by [`parse`].
+pub struct Entry {
+    pub id: u32,
+    pub path: String,
+    pub offset: u32,
+    pub len: u32,
+    pub crc: u32,
+}
+
+impl Pack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `data` at `path`, which must be unique, and hash to a unique id.
+    pub fn add(&mut self, path: String, data: Vec<u8>) -> Result<(), String> {
+        if path.len() > u8::MAX as usize {
+            return Err(format!("`{path}` is too long for the pack"));
+        }
+        if let Some((other, _)) = self.files.iter().find(|(p, _)| id(p) == id(&path)) {
+            return Err(if *other == path {
+                format!("`{path}` is in the pack twice")
+            } else {
+                format!("`{path}` has the same id as `{other}`, rename one of them")
+            });
+        }
+        self.files.push((path, data));
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let count: u16 = self
+            .files
+            .len()
+            .try_into()
+            .map_err(|_| format!("{} files is too many for a pack", self.files.len()))?;
+        let mut files: Vec<_> = self.files.iter().collect();
+        files.sort_by_key(|(path, _)| id(path));
+
+        let mut out = vec![0; HEADER_LEN + files.len() * ENTRY_LEN];
+        for (i, (path, data)) in files.iter().enumerate() {
+            out.resize(out.len().next_multiple_of(PAYLOAD_ALIGN), 0);
+            let entry = HEADER_LEN + i * ENTRY_LEN;
+            for (k, value) in [id(path), out.len() as u32, data.len() as u32, crc32(data)]
+                .into_iter()
+                .enumerate()
+            {
+                out[entry + k * 4..entry + k * 4 + 4].copy_from_slice(&value.to_le_bytes());
+            }
+            out.extend(data);
+        }
+        let names_at = out.len();
+        for (path, _) in &files {
+            out.push(path.len() as u8);
+            out.extend(path.as_bytes());
+        }
+        out.resize(out.len().next_multiple_of(4), 0);
+        if out.len() > u32::MAX as usize {
+            return Err(format!("{} bytes is too large for a pack", out.len()));
+        }
+
+        out[..4].copy_from_slice(MAGIC);
+        out[4..6].copy_from_slice(&VERSION.to_le_bytes());
+        out[6..8].copy_from_slice(&count.to_le_bytes());
+        out[8..12].copy_from_slice(&(names_at as u32).to_le_bytes());
+        let len = out.len() as u32;
+        out[12..16].copy_from_slice(&len.to_le_bytes());
+        Ok(out)
+    }
+}
+
+/// Reads the table of contents of `pack`, checking everything the game relies on: the header,
+/// the order of the table, ids matching paths, and payloads being aligned, in bounds, not
+/// overlapping and matching their CRC.
+pub fn parse(pack: &[u8]) -> Result<Vec<Entry>, String> {
+    let u16_at = |at: usize| u16::from_le_bytes(pack[at..at + 2].try_into().unwrap());
+    let u32_at = |at: usize| u32::from_le_bytes(pack[at..at + 4].try_into().unwrap());
+    if pack.len() < HEADER_LEN || !pack.starts_with(MAGIC) {
+        return Err("not an asset pack".into());
+    }
+    if u16_at(4) != VERSION {
+        return Err(format!("version {} is not {VERSION}", u16_at(4)));
+    }
+    let count = u16_at(6) as usize;
+    let names_at = u32_at(8) as usize;
+    if u32_at(12) as usize != pack.len() {
+        return Err(format!(
+            "the header says {} bytes, the file has {}",
+            u32_at(12),
+            pack.len()
+        ));
+    }
+    let toc_end = HEADER_LEN + count * ENTRY_LEN;
+    if toc_end > names_at || names_at > pack.len() {
+        return Err("the name table is out of bounds".into());
+    }
+
+    let mut names = pack[names_at..].iter();
+    let mut entries: Vec<Entry> = Vec::with_capacity(count);
+    for i in 0..count {
+        let at = HEADER_LEN + i * ENTRY_LEN;
+        let len = *names.next().ok_or("the name table is truncated")? as usize;
+        let name: Vec<u8> = names.by_ref().take(len).copied().collect();
+        let path = String::from_utf8(name).map_err(|_| format!("entry {i} has a bad name"))?;
+        let entry = Entry {
+            id: u32_at(at),
+            offset: u32_at(at + 4),
+            len: u32_at(at + 8),
+            crc: u32_at(at + 12),
+            path,
+        };
+        let err = |msg: &str| Err(format!("`{}` {msg}", entry.path));
+        if entry.id != id(&entry.path) {
+            return err("does not match its id");
+        }
+        if entries.last().is_some_and(|prev| prev.id >= entry.id) {
+            return err("is out of order");
+        }
+        let (start, end) = (
+            entry.offset as usize,
+            entry.offset as usize + entry.len as usize,
+        );
+        if start % PAYLOAD_ALIGN != 0 {
+            return err("is not aligned");
+        }
+        if start < toc_end || end > names_at {
+            return err("is out of bounds");
+        }
+        if entries
+            .iter()
+            .any(|e| start < e.offset as usize + e.len as usize && (e.offset as usize) < end)
+        {
+            return err("overlaps another asset");
+        }
+        if crc32(&pack[start..end]) != entry.crc {
+            return err("is corrupted, its CRC does not match");
+        }
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Pack {
+        let mut pack = Pack::new();
+        for (path, len) in [
+            ("sprites/a.img.bin", 5),
+            ("pal.bin", 64),
+            ("maps/b.map.bin", 33),
+        ] {
+            pack.add(path.into(), (0..len).map(|i| i as u8).collect())
+                .unwrap();
+        }
+        pack
+    }
+
+    #[test]
+    fn round_trips_sorted_and_aligned() {
+        let pack = sample();
+        let bytes = pack.to_bytes().unwrap();
+        let entries = parse(&bytes).unwrap();
+
+        assert_eq!(entries.len(), pack.files.len());
+        assert!(entries.windows(2).all(|pair| pair[0].id < pair[1].id));
+        for entry in &entries {
+            assert_eq!(entry.offset as usize % PAYLOAD_ALIGN, 0, "{}", entry.path);
+            let (_, data) = pack.files.iter().find(|(p, _)| *p == entry.path).unwrap();
+            let start = entry.offset as usize;
+            assert_eq!(&bytes[start..start + entry.len as usize], data.as_slice());
+            assert_eq!(entry.id, id(&entry.path));
+        }
+    }
+
+    #[test]
+    fn rejects_duplicate_ids() {
+        let mut pack = sample();
+        let err = pack.add("pal.bin".into(), vec![1]).unwrap_err();
+        assert!(err.contains("twice"), "{err}");
+    }
+
+    #[test]
+    fn detects_a_flipped_byte() {
+        let mut bytes = sample().to_bytes().unwrap();
+        let entry = &parse(&bytes).unwrap()[1];
+        let at = entry.offset as usize + entry.len as usize / 2;
+        bytes[at] ^= 1;
+        let err = parse(&bytes).err().unwrap();
+        assert!(err.contains("CRC"), "{err}");
+    }
+
+    #[test]
+    fn rejects_out_of_order_entries() {
+        let mut bytes = sample().to_bytes().unwrap();
+        // Swaps the first two table entries.
+        let (first, second) = bytes[HEADER_LEN..HEADER_LEN + 2 * ENTRY_LEN].split_at_mut(ENTRY_LEN);
+        first.swap_with_slice(second);
+        assert!(parse(&bytes).is_err());
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::image::Image;
+
+/// Converts an RGB888 color into the DS `xBBBBBGGGGGRRRRR` format.
+pub const fn rgb555([r, g, b]: [u8; 3]) -> u16 {
+    (r as u16 >> 3) | ((g as u16 >> 3) << 5) | ((b as u16 >> 3) << 10)
+}
+
+/// The opaque colors used by a set of images, after conversion to RGB555.
+pub fn color_set<'a>(images: impl IntoIterator<Item = &'a Image>) -> BTreeSet<u16> {
+    images
+        .into_iter()
+        .flat_map(|image| &image.pixels)
+        .filter(|&&px| !Image::is_transparent(px))
+        .map(|px| rgb555([px[0], px[1], px[2]]))
+        .collect()
+}
+
+/// How many colors each asset of a group can use.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Depth {
+    /// 8bpp, every asset shares one 256 color palette.
+    Bpp8,
+    /// 4bpp, every asset gets one of sixteen 16 color banks, which assets may share.
+    Bpp4,
+}
+
+impl Depth {
+    /// Colors an asset can use, besides the transparent one.
+    pub const fn max_colors(self) -> usize {
+        match self {
+            Depth::Bpp8 => Palette::MAX_COLORS - 1,
+            Depth::Bpp4 => Palette::BANK_COLORS - 1,
+        }
+    }
+}
+
+/// A 256 color palette where index 0 is the transparent color.
+///
+/// When used as sixteen 16 color banks, index 0 of each bank is transparent instead.
+pub struct Palette {
+    colors: Vec<u16>,
+}
+
+impl Palette {
+    pub const MAX_COLORS: usize = 256;
+    pub const BANK_COLORS: usize = 16;
+    pub const BANKS: usize = Self::MAX_COLORS / Self::BANK_COLORS;
+
+    pub fn new(transparent: [u8; 3]) -> Self {
+        Self {
+            colors: vec![rgb555(transparent)],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    /// Adds the colors of `set` missing from the palette.
+    ///
+    /// If they don't all fit, returns how many colors the palette would need and leaves it
+    /// untouched.
+    pub fn insert_all(&mut self, set: &BTreeSet<u16>) -> Result<(), usize> {
+        let missing: Vec<u16> = set
+            .iter()
+            .filter(|c| !self.colors[1..].contains(c))
+            .copied()
+            .collect();
+        let needed = self.colors.len() + missing.len();
+        if needed > Self::MAX_COLORS {
+            return Err(needed - 1);
+        }
+        self.colors.extend(missing);
+        Ok(())
+    }
+
+    /// Packs the color sets of a group's assets into banks, returning the bank of each asset.
+    ///
+    /// Assets with the most colors are placed first, each one in the first bank with room left
+    /// for the colors it doesn't share with it.
+    pub fn pack_banks(
+        transparent: [u8; 3],
+        sets: &[BTreeSet<u16>],
+    ) -> Result<(Self, Vec<u8>), BankError> {
+        let mut order: Vec<usize> = (0..sets.len()).collect();
+        order.sort_by_key(|&i| std::cmp::Reverse(sets[i].len()));
+
+        let mut banks: Vec<BTreeSet<u16>> = vec![];
+        let mut assignment = vec![0; sets.len()];
+        for i in order {
+            let set = &sets[i];
+            if set.len() > Depth::Bpp4.max_colors() {
+                return Err(BankError::TooManyColors {
+                    asset: i,
+                    colors: set.len(),
+                });
+            }
+            let bank = banks
+                .iter()
+                .position(|bank| bank.union(set).count() <= Depth::Bpp4.max_colors());
+            let bank = match bank {
+                Some(bank) => bank,
+                None if banks.len() < Self::BANKS => {
+                    banks.push(BTreeSet::new());
+                    banks.len() - 1
+                }
+                None => return Err(BankError::OutOfBanks { asset: i }),
+            };
+            banks[bank].extend(set);
+            assignment[i] = bank as u8;
+        }
+
+        let mut colors = Vec::with_capacity(banks.len() * Self::BANK_COLORS);
+        for bank in &banks {
+            colors.push(rgb555(transparent));
+            colors.extend(bank);
+            colors.resize(colors.len().next_multiple_of(Self::BANK_COLORS), 0);
+        }
+        if colors.is_empty() {
+            colors.push(rgb555(transparent));
+        }
+        Ok((Self { colors }, assignment))
+    }
+
+    /// Index of `px` in the palette, or in `bank` for 4bpp assets.
+    pub fn index_of(&self, px: [u8; 4], bank: Option<u8>) -> Option<u8> {
+        if Image::is_transparent(px) {
+            return Some(0);
+        }
+        let color = rgb555([px[0], px[1], px[2]]);
+        let start = bank.map_or(0, |b| b as usize * Self::BANK_COLORS);
+        let end = bank.map_or(self.colors.len(), |_| start + Self::BANK_COLORS);
+        // Index 0 is reserved, so an opaque pixel with the key color gets its own entry.
+        let entries = self.colors.get(start + 1..end)?;
+        let i = entries.iter().position(|&c| c == color)?;
+        Some(i as u8 + 1)
+    }
+
+    /// Palette data as expected by `Palette::load`, always 256 entries long.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::MAX_COLORS * 2);
+        for i in 0..Self::MAX_COLORS {
+            let color = self.colors.get(i).copied().unwrap_or(0);
+            bytes.extend_from_slice(&color.to_le_bytes());
+        }
+        bytes
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum BankError {
+    /// The asset at this index has more colors than fit in a bank.
+    TooManyColors { asset: usize, colors: usize },
+    /// Every bank was full by the time the asset at this index was placed.
+    OutOfBanks { asset: usize },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 3] = [255, 0, 255];
+
+    fn set(colors: std::ops::Range<u16>) -> BTreeSet<u16> {
+        colors.collect()
+    }
+
+    #[test]
+    fn overlapping_sets_share_a_bank() {
+        // 10 colors each, 15 together.
+        let (palette, banks) = Palette::pack_banks(KEY, &[set(1..11), set(6..16)]).unwrap();
+        assert_eq!(banks, [0, 0]);
+        assert_eq!(palette.len(), Palette::BANK_COLORS);
+        assert_eq!(palette.index_of([0; 4], Some(0)), Some(0));
+    }
+
+    #[test]
+    fn disjoint_sets_get_their_own_banks() {
+        let (palette, banks) = Palette::pack_banks(KEY, &[set(1..11), set(11..21)]).unwrap();
+        assert_eq!(banks, [0, 1]);
+        assert_eq!(palette.len(), 2 * Palette::BANK_COLORS);
+    }
+
+    #[test]
+    fn overflow_reports_the_asset() {
+        // Assets with the most colors are placed first, so the smallest one finds no bank left.
+        let mut sets: Vec<_> = (0..Palette::BANKS as u16)
+            .map(|i| set(i * 15 + 1..i * 15 + 16))
+            .collect();
+        sets.insert(3, set(1000..1010));
+        assert_eq!(
+            Palette::pack_banks(KEY, &sets).err(),
+            Some(BankError::OutOfBanks { asset: 3 })
+        );
+
+        let sets = [set(1..5), set(1..17)];
+        assert_eq!(
+            Palette::pack_banks(KEY, &sets).err(),
+            Some(BankError::TooManyColors {
+                asset: 1,
+                colors: 16
+            })
+        );
+    }
+}
@@ -0,0 +1,96 @@
+//! Entity prefabs, one TOML file each in `data/prefabs`, compiled into the `prefabs` module of
+//! the generated code and spawned with `src/prefab.rs`. A prefab is named after its file:
+//!
+//! ```toml
+//! # player.toml
+//! sprite = "Squid"     # stem of a sprite in data/, which brings its size and palette
+//! behaviour = "player" # a variant of `prefab::Behaviour`, "move" by default
+//!
+//! [physics]            # every field defaults to 0, max_speed to no limit
+//! velocity = [0.0, 0.0]
+//! gravity = 110.0
+//! acceleration = 100.0
+//! drag = 4.0
+//! max_speed = 50.0
+//! jump_speed = 55.0
+//! ```
+
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::AssetError;
+
+/// Behaviour of prefabs that don't set one, moving at their velocity.
+const DEFAULT_BEHAVIOUR: &str = "move";
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PrefabFile {
+    sprite: String,
+    behaviour: Option<String>,
+    #[serde(default)]
+    physics: Physics,
+}
+
+/// How an entity moves, in pixels and seconds.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct Physics {
+    /// Velocity it spawns with.
+    pub velocity: [f32; 2],
+    pub gravity: f32,
+    /// Horizontal acceleration when it moves on its own.
+    pub acceleration: f32,
+    /// How fast it slows down when it stops moving, as a fraction of its speed per second.
+    pub drag: f32,
+    /// Limit of its speed, `None` for no limit.
+    pub max_speed: Option<f32>,
+    /// Upwards speed it jumps with.
+    pub jump_speed: f32,
+}
+
+pub struct Prefab {
+    pub name: String,
+    /// Stem of the sprite.
+    pub sprite: String,
+    pub behaviour: String,
+    pub physics: Physics,
+}
+
+impl Prefab {
+    /// Reads the prefab at `path`, whose sprite has to be one of `sprites`.
+    pub fn open(path: &Path, sprites: &[&str]) -> Result<Self, AssetError> {
+        let err = |msg: String| AssetError::new(path, msg);
+        let text =
+            std::fs::read_to_string(path).map_err(|e| err(format!("could not open: {e}")))?;
+        let file: PrefabFile =
+            toml::from_str(&text).map_err(|e| err(format!("invalid prefab: {e}")))?;
+        if !sprites.contains(&file.sprite.as_str()) {
+            return Err(err(format!(
+                "there is no sprite `{}`, it can be one of {}",
+                file.sprite,
+                sprites.join(", ")
+            )));
+        }
+        let physics = &file.physics;
+        let values = [
+            physics.velocity[0],
+            physics.velocity[1],
+            physics.gravity,
+            physics.acceleration,
+            physics.drag,
+            physics.max_speed.unwrap_or(0.0),
+            physics.jump_speed,
+        ];
+        if values.iter().any(|v| !v.is_finite()) {
+            return Err(err("has physics values that aren't finite".into()));
+        }
+        Ok(Self {
+            name: path.file_stem().unwrap().to_string_lossy().into_owned(),
+            sprite: file.sprite,
+            behaviour: file.behaviour.unwrap_or_else(|| DEFAULT_BEHAVIOUR.into()),
+            physics: file.physics,
+        })
+    }
+}
@@ -0,0 +1,274 @@
+//! Pixelorama `.pxo` project import.
+//!
+//! A `.pxo` file is a zip archive with a `data.json` describing the project and one raw RGBA8
+//! buffer per cel at `image_data/frames/<frame>/layer_<layer>`, both 1-based.
+
+use std::{fs::File, io::Read, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AssetError,
+    collision::{Hitbox, Rect},
+    image::Image,
+};
+
+#[derive(Deserialize)]
+struct ProjectJson {
+    size_x: u32,
+    size_y: u32,
+    fps: f32,
+    frames: Vec<FrameJson>,
+    layers: Vec<LayerJson>,
+    #[serde(default)]
+    tags: Vec<TagJson>,
+}
+
+#[derive(Deserialize)]
+struct FrameJson {
+    cels: Vec<CelJson>,
+    /// Multiplier of `1 / fps`.
+    duration: f32,
+}
+
+#[derive(Deserialize)]
+struct CelJson {
+    opacity: f32,
+}
+
+#[derive(Deserialize)]
+struct LayerJson {
+    name: String,
+    visible: bool,
+    opacity: f32,
+    /// Index of the parent group layer, `-1` for top level layers.
+    parent: i32,
+    #[serde(rename = "type")]
+    kind: u32,
+    blend_mode: u32,
+}
+
+#[derive(Deserialize)]
+struct TagJson {
+    name: String,
+    from: u32,
+    to: u32,
+}
+
+const LAYER_PIXEL: u32 = 0;
+const LAYER_GROUP: u32 = 1;
+const LAYER_TILEMAP: u32 = 3;
+const BLEND_NORMAL: u32 = 0;
+/// Layers whose name starts with one of these hold hitboxes rather than art.
+const HITBOX_PREFIXES: [&str; 2] = ["hitbox", "hurtbox"];
+
+pub struct Frame {
+    pub image: Image,
+    pub duration_ms: u16,
+}
+
+/// A named range of frames, from Pixelorama's tags.
+#[derive(Serialize, Deserialize)]
+pub struct Tag {
+    pub name: String,
+    /// First frame, 0-based.
+    pub from: u16,
+    /// Last frame, inclusive.
+    pub to: u16,
+}
+
+pub struct Project {
+    pub width: u32,
+    pub height: u32,
+    pub frames: Vec<Frame>,
+    pub tags: Vec<Tag>,
+    /// From layers named after [`HITBOX_PREFIXES`], which aren't drawn whether visible or not.
+    pub hitboxes: Vec<Hitbox>,
+}
+
+impl Project {
+    pub fn open(path: &Path) -> Result<Self, AssetError> {
+        let err = |msg: String| AssetError::new(path, msg);
+        let file = File::open(path).map_err(|e| err(format!("could not open: {e}")))?;
+        let mut zip =
+            zip::ZipArchive::new(file).map_err(|e| err(format!("invalid pxo archive: {e}")))?;
+        let mut read = |name: &str| -> Result<Vec<u8>, AssetError> {
+            let mut entry = zip
+                .by_name(name)
+                .map_err(|e| err(format!("missing `{name}`: {e}")))?;
+            let mut buf = vec![];
+            entry
+                .read_to_end(&mut buf)
+                .map_err(|e| err(format!("could not read `{name}`: {e}")))?;
+            Ok(buf)
+        };
+
+        let json: ProjectJson = serde_json::from_slice(&read("data.json")?)
+            .map_err(|e| err(format!("invalid data.json: {e}")))?;
+
+        // Hidden groups hide their children, and group opacity applies to them too. Groups come
+        // after their children, so each layer walks up to the top level.
+        let mut layer_opacity = Vec::with_capacity(json.layers.len());
+        for layer in &json.layers {
+            let mut opacity = 1.0;
+            let mut at = layer;
+            // A chain longer than the layers would be a cycle.
+            for _ in 0..=json.layers.len() {
+                opacity *= if at.visible { at.opacity } else { 0.0 };
+                let Ok(parent) = usize::try_from(at.parent) else {
+                    break;
+                };
+                match json.layers.get(parent) {
+                    Some(group) if group.kind == LAYER_GROUP && !std::ptr::eq(group, at) => {
+                        at = group;
+                    }
+                    _ => return Err(err(format!("layer `{}` has a bad parent", layer.name))),
+                }
+            }
+            if at.parent >= 0 {
+                return Err(err(format!("layer `{}` has a bad parent", layer.name)));
+            }
+            layer_opacity.push(opacity);
+        }
+
+        let is_hitbox =
+            |layer: &LayerJson| HITBOX_PREFIXES.iter().any(|p| layer.name.starts_with(p));
+        let mut hitboxes: Vec<Hitbox> = (json.layers.iter())
+            .filter(|layer| is_hitbox(layer))
+            .map(|layer| Hitbox {
+                name: layer.name.clone(),
+                rects: vec![],
+            })
+            .collect();
+
+        let pixel_count = (json.size_x * json.size_y) as usize;
+        let (width, height) = (json.size_x, json.size_y);
+        let mut read_cel = |f: usize, l: usize| {
+            let data = read(&format!("image_data/frames/{}/layer_{}", f + 1, l + 1))?;
+            if data.len() != pixel_count * 4 {
+                return Err(err(format!(
+                    "cel {} of layer `{}` is not {width}x{height} RGBA8",
+                    f + 1,
+                    json.layers[l].name,
+                )));
+            }
+            Ok(data)
+        };
+        let mut frames = Vec::with_capacity(json.frames.len());
+        for (f, frame) in json.frames.iter().enumerate() {
+            let mut canvas = vec![[0u8; 4]; pixel_count];
+            let mut frame_hitboxes = hitboxes.iter_mut();
+            for (l, layer) in json.layers.iter().enumerate() {
+                if is_hitbox(layer) {
+                    if layer.kind != LAYER_PIXEL {
+                        return Err(err(format!("layer `{}` is not a pixel layer", layer.name)));
+                    }
+                    let data = read_cel(f, l)?;
+                    let pixels = data.chunks_exact(4).map(|p| [p[0], p[1], p[2], p[3]]);
+                    let image = Image::from_rgba(width, height, pixels.collect());
+                    let hitbox = frame_hitboxes.next().unwrap();
+                    hitbox.rects.push(Rect::bounds(&image));
+                    continue;
+                }
+                let opacity = layer_opacity[l] * frame.cels.get(l).map_or(1.0, |c| c.opacity);
+                if layer.kind == LAYER_GROUP || opacity <= 0.0 {
+                    continue;
+                }
+                if layer.kind != LAYER_PIXEL && layer.kind != LAYER_TILEMAP {
+                    return Err(err(format!("layer `{}` is not a pixel layer", layer.name)));
+                }
+                if layer.blend_mode != BLEND_NORMAL {
+                    return Err(err(format!(
+                        "layer `{}` uses a blend mode other than normal",
+                        layer.name
+                    )));
+                }
+                let data = read_cel(f, l)?;
+                for (dst, src) in canvas.iter_mut().zip(data.chunks_exact(4)) {
+                    *dst = blend_over(*dst, [src[0], src[1], src[2], src[3]], opacity);
+                }
+            }
+            let duration_ms = (frame.duration * 1000.0 / json.fps).round() as u16;
+            frames.push(Frame {
+                image: Image::from_rgba(json.size_x, json.size_y, canvas),
+                duration_ms,
+            });
+        }
+
+        let mut tags = Vec::with_capacity(json.tags.len());
+        for tag in json.tags {
+            if tag.from == 0 || tag.from > tag.to || tag.to as usize > frames.len() {
+                return Err(err(format!("tag `{}` has a bad frame range", tag.name)));
+            }
+            tags.push(Tag {
+                name: tag.name,
+                from: tag.from as u16 - 1,
+                to: tag.to as u16 - 1,
+            });
+        }
+
+        Ok(Self {
+            width: json.size_x,
+            height: json.size_y,
+            frames,
+            tags,
+            hitboxes,
+        })
+    }
+}
+
+impl From<Image> for Project {
+    /// A still image is a project with a single frame and no tags.
+    fn from(image: Image) -> Self {
+        Self {
+            width: image.width,
+            height: image.height,
+            frames: vec![Frame {
+                image,
+                duration_ms: 0,
+            }],
+            tags: vec![],
+            hitboxes: vec![],
+        }
+    }
+}
+
+/// Draws `src`, with its alpha scaled by `opacity`, over `dst`.
+fn blend_over(dst: [u8; 4], src: [u8; 4], opacity: f32) -> [u8; 4] {
+    let sa = src[3] as f32 / 255.0 * opacity;
+    let da = dst[3] as f32 / 255.0;
+    let a = sa + da * (1.0 - sa);
+    if a <= 0.0 {
+        return [0; 4];
+    }
+    let mut out = [0; 4];
+    for c in 0..3 {
+        let v = (src[c] as f32 * sa + dst[c] as f32 * da * (1.0 - sa)) / a;
+        out[c] = v.round() as u8;
+    }
+    out[3] = (a * 255.0).round() as u8;
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures")
+            .join(name)
+    }
+
+    /// `groups.pxo` is 3x1, each pixel drawn by a layer whose groups come after it:
+    /// a red one in a hidden group, a blue one in a group at half opacity, and a green one in a
+    /// group inside that one.
+    #[test]
+    fn groups_apply_to_the_layers_before_them() {
+        let project = Project::open(&fixture("groups.pxo")).unwrap();
+        let pixels = &project.frames[0].image.pixels;
+        assert_eq!(pixels[0][3], 0, "the hidden group's layer is drawn");
+        assert_eq!(pixels[1], [0, 0, 255, 128]);
+        assert_eq!(pixels[2], [0, 255, 0, 128]);
+    }
+}
@@ -0,0 +1,308 @@
+//! WAV conversion to the sample formats the DS sound channels play, and the sound bank the game
+//! loads them from.
+//!
+//! A bank is, all integers little endian:
+//!
+//! - An 8 byte header: [`BANK_MAGIC`], [`BANK_VERSION`] (u8), a padding byte and the sound count
+//!   (u16).
+//! - 16 bytes per sound, in id order: the offset of its data in the bank (u32), the length of
+//!   its data (u32, a multiple of 4), its sample rate (u16), its [`SoundFormat`] (u8, numbered
+//!   like libnds's `SoundFormat`), flags (u8, bit 0 set if it loops), and where it loops back to
+//!   (u32, in words from the start of its data, which is what the hardware counts in).
+//! - The data of every sound, each starting on a word.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{AssetError, codegen::SoundInfo, manifest::AssetOptions};
+
+pub const BANK_MAGIC: &[u8; 4] = b"DHSB";
+pub const BANK_VERSION: u8 = 1;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SoundFormat {
+    /// Signed 8 bit samples.
+    Pcm8,
+    /// Signed 16 bit samples.
+    Pcm16,
+    /// IMA-ADPCM, 4 bits per sample after a word holding the first sample and step index.
+    Adpcm,
+}
+
+impl SoundFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Pcm8 => "PCM8",
+            Self::Pcm16 => "PCM16",
+            Self::Adpcm => "IMA-ADPCM",
+        }
+    }
+
+    /// Value of libnds's `SoundFormat`.
+    fn id(self) -> u8 {
+        match self {
+            Self::Pcm8 => 0,
+            Self::Pcm16 => 1,
+            Self::Adpcm => 2,
+        }
+    }
+
+    /// Loop points are in words, so they can only fall on multiples of this.
+    fn samples_per_word(self) -> usize {
+        match self {
+            Self::Pcm8 => 4,
+            Self::Pcm16 => 2,
+            Self::Adpcm => 8,
+        }
+    }
+}
+
+/// A WAV mixed down to mono.
+pub struct Sound {
+    pub name: String,
+    pub rate: u32,
+    pub samples: Vec<i16>,
+    /// Sample played again once the last one is, `None` if the sound plays once.
+    pub loop_start: Option<usize>,
+}
+
+impl Sound {
+    /// Reads the WAV at `path`, looping it as its `smpl` chunk says unless `options` says
+    /// otherwise, or from the start if `loop_by_default`.
+    pub fn open(
+        path: &Path,
+        options: &AssetOptions,
+        loop_by_default: bool,
+    ) -> Result<Self, AssetError> {
+        let err = |msg: String| AssetError::new(path, msg);
+        let bytes = std::fs::read(path).map_err(|e| err(format!("could not open: {e}")))?;
+        let mut sound = parse_wav(&bytes).map_err(err)?;
+        sound.name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        if let Some(start) = options.loop_start {
+            sound.loop_start = Some(start as usize);
+        } else if sound.loop_start.is_none() && loop_by_default {
+            sound.loop_start = Some(0);
+        }
+        if sound
+            .loop_start
+            .is_some_and(|start| start >= sound.samples.len())
+        {
+            return Err(err(format!(
+                "loops from sample {}, past its end",
+                sound.loop_start.unwrap()
+            )));
+        }
+        if sound.rate > u16::MAX as u32 {
+            return Err(err(format!(
+                "has a sample rate of {}Hz, the hardware plays up to {}Hz",
+                sound.rate,
+                u16::MAX
+            )));
+        }
+        Ok(sound)
+    }
+
+    pub fn duration_secs(&self) -> f32 {
+        self.samples.len() as f32 / self.rate as f32
+    }
+
+    /// The samples in `format`, and the word the sound loops back to.
+    pub fn encode(&self, format: SoundFormat) -> Result<(Vec<u8>, Option<u32>), String> {
+        let per_word = format.samples_per_word();
+        // Silence in front moves the loop point onto a word, a few samples earlier is inaudible.
+        let lead = self
+            .loop_start
+            .map_or(0, |start| (per_word - start % per_word) % per_word);
+        let mut samples = vec![0; lead];
+        samples.extend(&self.samples);
+        samples.resize(samples.len().next_multiple_of(per_word), 0);
+        let loop_start = self.loop_start.map(|start| start + lead);
+
+        let (data, loop_word) = match format {
+            SoundFormat::Pcm8 => (
+                samples.iter().map(|&s| (s >> 8) as u8).collect(),
+                loop_start.map(|start| start / 4),
+            ),
+            SoundFormat::Pcm16 => (
+                samples.iter().flat_map(|s| s.to_le_bytes()).collect(),
+                loop_start.map(|start| start / 2),
+            ),
+            // The header word comes before the first sample.
+            SoundFormat::Adpcm => (adpcm(&samples), loop_start.map(|start| 1 + start / 8)),
+        };
+        match loop_word {
+            Some(word) if word > u16::MAX as usize => Err(format!(
+                "loops from word {word}, libnds can only loop from the first {}",
+                u16::MAX
+            )),
+            _ => Ok((data, loop_word.map(|word| word as u32))),
+        }
+    }
+}
+
+/// Parses an uncompressed 8, 16 or 24 bit WAV, mixing its channels down.
+fn parse_wav(bytes: &[u8]) -> Result<Sound, String> {
+    if bytes.len() < 12 || &bytes[..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err("is not a WAV file".into());
+    }
+    let u16_at = |data: &[u8], at: usize| u16::from_le_bytes([data[at], data[at + 1]]);
+    let u32_at = |data: &[u8], at: usize| u32::from_le_bytes(data[at..at + 4].try_into().unwrap());
+
+    let mut format = None;
+    let mut data = None;
+    let mut loop_start = None;
+    let mut at = 12;
+    while at + 8 <= bytes.len() {
+        let id = &bytes[at..at + 4];
+        let len = u32_at(bytes, at + 4) as usize;
+        let chunk = bytes
+            .get(at + 8..at + 8 + len)
+            .ok_or("has a truncated chunk")?;
+        match id {
+            b"fmt " if len >= 16 => {
+                let mut tag = u16_at(chunk, 0);
+                // WAVE_FORMAT_EXTENSIBLE keeps the real tag at the start of its subformat GUID.
+                if tag == 0xFFFE && len >= 26 {
+                    tag = u16_at(chunk, 24);
+                }
+                if tag != 1 {
+                    return Err(format!(
+                        "is compressed (format {tag:#x}), export it as uncompressed PCM"
+                    ));
+                }
+                format = Some((u16_at(chunk, 2), u32_at(chunk, 4), u16_at(chunk, 14)));
+            }
+            b"data" => data = Some(chunk),
+            // The first loop of the sampler chunk, whose points are sample frames.
+            b"smpl" if len >= 36 + 24 && u32_at(chunk, 28) > 0 => {
+                loop_start = Some((u32_at(chunk, 36 + 8), u32_at(chunk, 36 + 12)));
+            }
+            _ => {}
+        }
+        // Chunks are padded to an even size.
+        at += 8 + len + len % 2;
+    }
+
+    let (channels, rate, bits) = format.ok_or("has no fmt chunk")?;
+    let data = data.ok_or("has no data chunk")?;
+    let width = match bits {
+        8 | 16 | 24 => bits as usize / 8,
+        _ => {
+            return Err(format!(
+                "has {bits} bit samples, only 8, 16 and 24 are supported"
+            ));
+        }
+    };
+    if channels == 0 {
+        return Err("has no channels".into());
+    }
+    let mut samples: Vec<i16> = data
+        .chunks_exact(width * channels as usize)
+        .map(|frame| {
+            let sum: i32 = frame
+                .chunks_exact(width)
+                .map(|s| match width {
+                    1 => (s[0] as i32 - 0x80) << 8,
+                    2 => i16::from_le_bytes([s[0], s[1]]) as i32,
+                    _ => i16::from_le_bytes([s[1], s[2]]) as i32,
+                })
+                .sum();
+            (sum / channels as i32) as i16
+        })
+        .collect();
+    // Whatever follows the loop would never be heard.
+    if let Some((_, end)) = loop_start {
+        samples.truncate(end as usize + 1);
+    }
+    Ok(Sound {
+        name: String::new(),
+        rate,
+        samples,
+        loop_start: loop_start.map(|(start, _)| start as usize),
+    })
+}
+
+const ADPCM_INDEX_TABLE: [i32; 8] = [-1, -1, -1, -1, 2, 4, 6, 8];
+const ADPCM_STEP_TABLE: [i32; 89] = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 21, 23, 25, 28, 31, 34, 37, 41, 45, 50, 55, 60, 66,
+    73, 80, 88, 97, 107, 118, 130, 143, 157, 173, 190, 209, 230, 253, 279, 307, 337, 371, 408, 449,
+    494, 544, 598, 658, 724, 796, 876, 963, 1060, 1166, 1282, 1411, 1552, 1707, 1878, 2066, 2272,
+    2499, 2749, 3024, 3327, 3660, 4026, 4428, 4871, 5358, 5894, 6484, 7132, 7845, 8630, 9493,
+    10442, 11487, 12635, 13899, 15289, 16818, 18500, 20350, 22385, 24623, 27086, 29794, 32767,
+];
+
+/// IMA-ADPCM as the DS decodes it: a header word with the first sample and step index, then a
+/// nibble per sample, low nibble first.
+///
+/// Each nibble is picked by running the hardware's decoder, so rounding errors don't add up.
+fn adpcm(samples: &[i16]) -> Vec<u8> {
+    let mut predicted = samples.first().copied().unwrap_or(0) as i32;
+    let mut index = 0;
+    let mut out = Vec::with_capacity(4 + samples.len() / 2);
+    out.extend((predicted as i16).to_le_bytes());
+    out.extend([0, 0]);
+    let mut nibbles = samples.iter().map(|&sample| {
+        let step = ADPCM_STEP_TABLE[index as usize];
+        let delta = sample as i32 - predicted;
+        let mut nibble = if delta < 0 { 8 } else { 0 };
+        let mut magnitude = delta.abs();
+        let mut diff = step >> 3;
+        for (bit, part) in [(4, step), (2, step >> 1), (1, step >> 2)] {
+            if magnitude >= part {
+                nibble |= bit;
+                magnitude -= part;
+                diff += part;
+            }
+        }
+        // The hardware clamps to ±0x7FFF, not -0x8000.
+        predicted = if nibble & 8 != 0 {
+            (predicted - diff).max(-0x7FFF)
+        } else {
+            (predicted + diff).min(0x7FFF)
+        };
+        index = (index + ADPCM_INDEX_TABLE[nibble as usize & 7]).clamp(0, 88);
+        nibble
+    });
+    while let Some(low) = nibbles.next() {
+        let high = nibbles.next().unwrap_or(0);
+        out.push(low | high << 4);
+    }
+    out
+}
+
+/// Sounds waiting to be written to a bank, in id order.
+#[derive(Default)]
+pub struct Bank {
+    entries: Vec<(Vec<u8>, u32, SoundFormat, Option<u32>)>,
+}
+
+impl Bank {
+    /// Adds a sound encoded by [`Sound::encode`].
+    pub fn add(&mut self, sound: &SoundInfo, data: Vec<u8>) {
+        self.entries
+            .push((data, sound.rate, sound.format, sound.loop_word));
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend(BANK_MAGIC);
+        out.extend([BANK_VERSION, 0]);
+        out.extend((self.entries.len() as u16).to_le_bytes());
+        let mut offset = out.len() + self.entries.len() * 16;
+        for (data, rate, format, loop_word) in &self.entries {
+            out.extend((offset as u32).to_le_bytes());
+            out.extend((data.len() as u32).to_le_bytes());
+            out.extend((*rate as u16).to_le_bytes());
+            out.extend([format.id(), loop_word.is_some() as u8]);
+            out.extend(loop_word.unwrap_or(0).to_le_bytes());
+            offset += data.len().next_multiple_of(4);
+        }
+        for (data, ..) in &self.entries {
+            out.extend(data);
+            out.resize(out.len().next_multiple_of(4), 0);
+        }
+        out
+    }
+}
@@ -0,0 +1,301 @@
+//! Tiled `.tmx` map import.
+//!
+//! Maps must be orthogonal and finite, with CSV layer data. Tilesets must be a single image,
+//! either embedded in the map or in a `.tsx` file.
+//!
+//! A tile layer named `collision` becomes the collision grid instead of a background: each cell
+//! holds the index of its tile in the tileset plus one. Objects become entities, their class
+//! (or name, if they have none) being the entity kind.
+
+use std::{path::Path, str::FromStr};
+
+use roxmltree::{Document, Node};
+
+use crate::{
+    AssetError,
+    image::Image,
+    level::{self, Collision, Entity, Flip, Level, Property},
+};
+
+const FLIP_H: u32 = 0x8000_0000;
+const FLIP_V: u32 = 0x4000_0000;
+const FLIP_DIAGONAL: u32 = 0x2000_0000;
+/// Every flag, including the one for hexagonal maps.
+const FLAGS: u32 = 0xF000_0000;
+
+struct Tileset {
+    first_gid: u32,
+    tile_width: u32,
+    tile_height: u32,
+    spacing: u32,
+    margin: u32,
+    columns: u32,
+    tile_count: u32,
+    image: Image,
+}
+
+pub fn open(path: &Path) -> Result<Level, AssetError> {
+    let err = |msg: String| AssetError::new(path, msg);
+    let text = std::fs::read_to_string(path).map_err(|e| err(format!("could not open: {e}")))?;
+    let doc = Document::parse(&text).map_err(|e| err(format!("invalid tmx: {e}")))?;
+    let map = doc.root_element();
+    if map.attribute("orientation") != Some("orthogonal") {
+        return Err(err("only orthogonal maps are supported".into()));
+    }
+    if map.attribute("infinite") == Some("1") {
+        return Err(err("infinite maps are not supported".into()));
+    }
+    let cols: u32 = attr(map, "width").map_err(err)?;
+    let rows: u32 = attr(map, "height").map_err(err)?;
+    let tile_width: u32 = attr(map, "tilewidth").map_err(err)?;
+    let tile_height: u32 = attr(map, "tileheight").map_err(err)?;
+
+    let dir = path.parent().unwrap();
+    let mut tilesets = vec![];
+    for node in map.children().filter(|n| n.has_tag_name("tileset")) {
+        tilesets.push(open_tileset(dir, node)?);
+    }
+
+    let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+    let mut level = Level::new(name, cols * tile_width, rows * tile_height);
+    for node in map.descendants().filter(|n| n.is_element()) {
+        if node
+            .ancestors()
+            .any(|n| n.attribute("visible") == Some("0"))
+        {
+            continue;
+        }
+        let layer_name = node.attribute("name").unwrap_or_default();
+        match node.tag_name().name() {
+            "layer" => {
+                let gids =
+                    layer_data(node).map_err(|e| err(format!("layer `{layer_name}` {e}")))?;
+                if gids.len() != (cols * rows) as usize {
+                    return Err(err(format!(
+                        "layer `{layer_name}` is not {cols}x{rows} tiles"
+                    )));
+                }
+                if layer_name.eq_ignore_ascii_case("collision") {
+                    if level.collision.is_some() {
+                        return Err(err("has more than one collision layer".into()));
+                    }
+                    let cells = gids
+                        .iter()
+                        .map(|&gid| match find_tileset(&tilesets, gid) {
+                            Some(tileset) => {
+                                let id = (gid & !FLAGS) - tileset.first_gid;
+                                (id + 1).min(u8::MAX as u32) as u8
+                            }
+                            None => 0,
+                        })
+                        .collect();
+                    level.collision = Some(Collision {
+                        cell_width: tile_width,
+                        cell_height: tile_height,
+                        cols,
+                        rows,
+                        cells,
+                    });
+                    continue;
+                }
+                let mut canvas = level.canvas();
+                for (i, &gid) in gids.iter().enumerate() {
+                    if gid & !FLAGS == 0 {
+                        continue;
+                    }
+                    let tileset = find_tileset(&tilesets, gid).ok_or_else(|| {
+                        err(format!("layer `{layer_name}` uses unknown tile {gid}"))
+                    })?;
+                    let id = (gid & !FLAGS) - tileset.first_gid;
+                    let flip = Flip {
+                        h: gid & FLIP_H != 0,
+                        v: gid & FLIP_V != 0,
+                        diagonal: gid & FLIP_DIAGONAL != 0,
+                    };
+                    let (col, row) = (i as u32 % cols, i as u32 / cols);
+                    // Tiles taller than the map's are anchored to the bottom of their cell.
+                    let x = (col * tile_width) as i32;
+                    let y = ((row + 1) * tile_height) as i32 - tileset.tile_height as i32;
+                    level::draw_tile(
+                        &mut canvas,
+                        (x, y),
+                        &tileset.image,
+                        tileset.tile_origin(id),
+                        (tileset.tile_width, tileset.tile_height),
+                        flip,
+                    );
+                }
+                level.layers.push(canvas);
+            }
+            "objectgroup" => {
+                for object in node.children().filter(|n| n.has_tag_name("object")) {
+                    level.entities.push(object_entity(object).map_err(|e| {
+                        err(format!(
+                            "object {} of layer `{layer_name}` {e}",
+                            object.attribute("id").unwrap_or("?")
+                        ))
+                    })?);
+                }
+            }
+            "imagelayer" => {
+                return Err(err(format!(
+                    "image layer `{layer_name}` is not supported, use a tile layer"
+                )));
+            }
+            _ => {}
+        }
+    }
+    // Tiled lists layers bottom to top.
+    level.layers.reverse();
+    Ok(level)
+}
+
+fn open_tileset(dir: &Path, node: Node) -> Result<Tileset, AssetError> {
+    let first_gid = attr(node, "firstgid").map_err(|e| AssetError::new(dir, e))?;
+    let Some(source) = node.attribute("source") else {
+        return parse_tileset(dir, node, first_gid).map_err(|e| AssetError::new(dir, e));
+    };
+    let path = dir.join(source);
+    crate::cache::track(&path);
+    let err = |msg: String| AssetError::new(&path, msg);
+    let text = std::fs::read_to_string(&path).map_err(|e| err(format!("could not open: {e}")))?;
+    let doc = Document::parse(&text).map_err(|e| err(format!("invalid tsx: {e}")))?;
+    parse_tileset(path.parent().unwrap(), doc.root_element(), first_gid).map_err(err)
+}
+
+/// Parses a `<tileset>` element, whose image path is relative to `dir`.
+fn parse_tileset(dir: &Path, node: Node, first_gid: u32) -> Result<Tileset, String> {
+    let name = node.attribute("name").unwrap_or_default();
+    let image_node = node
+        .children()
+        .find(|n| n.has_tag_name("image"))
+        .ok_or_else(|| format!("tileset `{name}` is not a single image"))?;
+    let source = image_node
+        .attribute("source")
+        .ok_or_else(|| format!("tileset `{name}` has no image source"))?;
+    let path = dir.join(source);
+    crate::cache::track(&path);
+    let mut image = Image::open(&path).map_err(|e| e.to_string())?;
+    if let Some(trans) = image_node.attribute("trans") {
+        let key = u32::from_str_radix(trans.trim_start_matches('#'), 16)
+            .map_err(|_| format!("tileset `{name}` has a bad transparent color"))?;
+        let [_, r, g, b] = key.to_be_bytes();
+        image.apply_color_key([r, g, b]);
+    }
+    Ok(Tileset {
+        first_gid,
+        tile_width: attr(node, "tilewidth")?,
+        tile_height: attr(node, "tileheight")?,
+        spacing: attr_or(node, "spacing", 0)?,
+        margin: attr_or(node, "margin", 0)?,
+        columns: attr(node, "columns")?,
+        tile_count: attr(node, "tilecount")?,
+        image,
+    })
+}
+
+impl Tileset {
+    /// Top left corner of tile `id` in the tileset's image.
+    fn tile_origin(&self, id: u32) -> (u32, u32) {
+        let (col, row) = (id % self.columns.max(1), id / self.columns.max(1));
+        (
+            self.margin + col * (self.tile_width + self.spacing),
+            self.margin + row * (self.tile_height + self.spacing),
+        )
+    }
+}
+
+/// The tileset `gid` belongs to, `None` for empty cells.
+fn find_tileset(tilesets: &[Tileset], gid: u32) -> Option<&Tileset> {
+    let gid = gid & !FLAGS;
+    tilesets
+        .iter()
+        .rev()
+        .find(|t| t.first_gid <= gid)
+        .filter(|t| gid - t.first_gid < t.tile_count)
+}
+
+fn layer_data(layer: Node) -> Result<Vec<u32>, String> {
+    let data = layer
+        .children()
+        .find(|n| n.has_tag_name("data"))
+        .ok_or("has no data")?;
+    if data.attribute("encoding") != Some("csv") {
+        return Err(
+            "is not CSV encoded, change the tile layer format in the map's properties".into(),
+        );
+    }
+    data.text()
+        .unwrap_or_default()
+        .split(',')
+        .map(|gid| {
+            gid.trim()
+                .parse()
+                .map_err(|_| format!("has a bad tile `{}`", gid.trim()))
+        })
+        .collect()
+}
+
+fn object_entity(object: Node) -> Result<Entity, String> {
+    let kind = ["type", "class", "name"]
+        .iter()
+        .filter_map(|a| object.attribute(*a))
+        .find(|kind| !kind.is_empty())
+        .ok_or("has neither a class nor a name")?;
+    if object.has_attribute("template") {
+        return Err("uses a template, which is not supported".into());
+    }
+    let x: f32 = attr(object, "x")?;
+    let mut y: f32 = attr(object, "y")?;
+    let width: f32 = attr_or(object, "width", 0.0)?;
+    let height: f32 = attr_or(object, "height", 0.0)?;
+    // Tile objects are positioned by their bottom left corner.
+    if object.has_attribute("gid") {
+        y -= height;
+    }
+
+    let mut properties = vec![];
+    let nodes = object
+        .children()
+        .filter(|n| n.has_tag_name("properties"))
+        .flat_map(|n| n.children().filter(|n| n.has_tag_name("property")));
+    for node in nodes {
+        let name = node.attribute("name").unwrap_or_default();
+        let value = node.attribute("value").or(node.text()).unwrap_or_default();
+        let bad = || format!("has a bad value for property `{name}`");
+        let property = match node.attribute("type").unwrap_or("string") {
+            "int" | "object" => Property::Int(value.parse().map_err(|_| bad())?),
+            "float" => Property::Float(value.parse().map_err(|_| bad())?),
+            "bool" => Property::Bool(value.parse().map_err(|_| bad())?),
+            "string" | "file" | "color" => Property::String(value.to_owned()),
+            other => return Err(format!("has property `{name}` of unsupported type {other}")),
+        };
+        properties.push((name.to_owned(), property));
+    }
+
+    Ok(Entity {
+        kind: kind.to_owned(),
+        x: x.round() as i32,
+        y: y.round() as i32,
+        width: width.round() as u32,
+        height: height.round() as u32,
+        properties,
+    })
+}
+
+fn attr<T: FromStr>(node: Node, name: &str) -> Result<T, String> {
+    let value = node
+        .attribute(name)
+        .ok_or_else(|| format!("<{}> has no `{name}`", node.tag_name().name()))?;
+    value
+        .parse()
+        .map_err(|_| format!("<{}> has a bad `{name}`: {value}", node.tag_name().name()))
+}
+
+fn attr_or<T: FromStr>(node: Node, name: &str, default: T) -> Result<T, String> {
+    if node.has_attribute(name) {
+        attr(node, name)
+    } else {
+        Ok(default)
+    }
+}
@@ -0,0 +1,57 @@
+//! Tests of what only the DS can check, like reading nitroFS and the BIOS decompressing assets.
+//! Run in an emulator by `just test-ds`, reporting through `libnds::testing`.
+
+use super::*;
+
+fn mount() -> Result<(), Box<dyn Error>> {
+    resources::mount(resources::Pack::open(assets::PACK)?);
+    Ok(())
+}
+
+#[test_case]
+fn loads_every_prefab_sprite() -> Result<(), Box<dyn Error>> {
+    mount()?;
+    for prefab in assets::prefabs::ALL {
+        let sprite = prefab.sprite;
+        let texture = sprite.load()?;
+        let len = sprite.frames.len().max(1) * sprite.frame_len();
+        if texture.img.len() != len {
+            return Err(alloc::format!(
+                "{} has {} bytes of frames instead of {len}",
+                prefab.name,
+                texture.img.len()
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
+#[test_case]
+fn level_has_a_player() -> Result<(), Box<dyn Error>> {
+    mount()?;
+    let level = assets::levels::LEVEL1.load()?;
+    let player = level
+        .spawns
+        .iter()
+        .filter_map(|spawn| prefab::find(&spawn.kind))
+        .any(|prefab| prefab.behaviour == Behaviour::Player);
+    if !player {
+        return Err("LEVEL1 has no player spawn point".into());
+    }
+    Ok(())
+}
+
+#[test_case]
+fn plays_every_sound() -> Result<(), Box<dyn Error>> {
+    mount()?;
+    sound::enable();
+    let sounds = assets::sounds::BANK.load()?;
+    for sound in 0..sounds.len() {
+        let channel = sounds
+            .play(sound, 0, 64)
+            .ok_or_else(|| alloc::format!("no channel to play sound {sound} on"))?;
+        channel.stop();
+    }
+    Ok(())
+}
@@ -0,0 +1,278 @@
+//! Levels converted by the build script from Tiled and LDtk, see `build/level.rs` for the format.
+
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::{error::Error, fmt, ops::Range};
+
+use libnds::{
+    background::{self as bg, Background},
+    engine::Screen,
+    resources::{self, AssetId, FileError},
+    texture::Palette,
+    video::{Layers, Text, VideoError},
+    vram::Vram,
+};
+
+use crate::vec2;
+
+const MAGIC: &[u8; 4] = b"DHLV";
+const VERSION: u8 = 1;
+const PALETTE_BYTES: usize = 256 * 2;
+const TILE_BYTES: usize = 8 * 8;
+/// Layers share one tileset, after the maps of up to four 512x512 layers.
+const TILE_BASE: i32 = 2;
+/// Map bases are in 2KB units, enough for a 256x256 map, so each layer gets room for 512x512.
+const MAP_BASE_STRIDE: i32 = 4;
+/// Sizes a layer can have, by their index in the file, with the length of their map.
+const TEXT_SIZES: [(bg::TextSize, usize); 4] = [
+    (bg::TextSize::T256x256, 2048),
+    (bg::TextSize::T512x256, 4096),
+    (bg::TextSize::T256x512, 4096),
+    (bg::TextSize::T512x512, 8192),
+];
+
+/// A level converted by the build script.
+pub struct LevelAsset {
+    pub id: AssetId,
+}
+
+impl LevelAsset {
+    pub fn load(&'static self) -> Result<Level, LevelError> {
+        Level::parse(resources::read_asset(self.id)?)
+    }
+}
+
+#[derive(Debug)]
+pub enum LevelError {
+    File(FileError),
+    /// The file is not a level, or was made by a different version of the build script.
+    Invalid(&'static str),
+}
+
+impl From<FileError> for LevelError {
+    fn from(e: FileError) -> Self {
+        Self::File(e)
+    }
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File(e) => write!(f, "{e}"),
+            Self::Invalid(msg) => write!(f, "LevelError: {msg}"),
+        }
+    }
+}
+
+impl Error for LevelError {}
+
+pub struct Level {
+    data: Box<[u8]>,
+    /// Size in pixels.
+    pub width: u16,
+    pub height: u16,
+    palette: Range<usize>,
+    tiles: Range<usize>,
+    /// Topmost first.
+    layers: Vec<(bg::TextSize, Range<usize>)>,
+    cell_size: (u8, u8),
+    cols: u16,
+    rows: u16,
+    cells: Range<usize>,
+    pub spawns: Vec<Spawn>,
+}
+
+/// Where an entity starts, and whatever the level designer set on it.
+pub struct Spawn {
+    pub kind: String,
+    /// Top left corner.
+    pub pos: vec2,
+    pub props: Vec<(String, Prop)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Prop {
+    Int(i32),
+    Float(f32),
+    Bool(bool),
+    Str(String),
+}
+
+impl Spawn {
+    pub fn prop(&self, name: &str) -> Option<&Prop> {
+        self.props.iter().find(|(n, _)| n == name).map(|(_, p)| p)
+    }
+
+    /// A numeric property, whether it was set as an int or a float.
+    pub fn float(&self, name: &str) -> Option<f32> {
+        match self.prop(name)? {
+            Prop::Int(value) => Some(*value as f32),
+            Prop::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl Level {
+    fn parse(data: Box<[u8]>) -> Result<Self, LevelError> {
+        let mut r = Reader {
+            data: &data,
+            pos: 0,
+        };
+        if r.bytes()? != *MAGIC {
+            return Err(LevelError::Invalid("not a level"));
+        }
+        if r.u8()? != VERSION {
+            return Err(LevelError::Invalid("unsupported version"));
+        }
+        let layer_count = r.u8()?;
+        let entity_count = r.u16()?;
+        let width = r.u16()?;
+        let height = r.u16()?;
+        let cell_size = (r.u8()?, r.u8()?);
+        let cols = r.u16()?;
+        let rows = r.u16()?;
+        let tile_count = r.u16()?;
+        let palette = r.take(PALETTE_BYTES)?;
+        let tiles = r.take(tile_count as usize * TILE_BYTES)?;
+        let mut layers = Vec::with_capacity(layer_count as usize);
+        for _ in 0..layer_count {
+            let &(size, map_len) = TEXT_SIZES
+                .get(r.u8()? as usize)
+                .ok_or(LevelError::Invalid("bad layer size"))?;
+            r.take(3)?;
+            layers.push((size, r.take(map_len)?));
+        }
+        let cells = r.take(cols as usize * rows as usize)?;
+        r.take(r.pos.next_multiple_of(4) - r.pos)?;
+
+        let mut spawns = Vec::with_capacity(entity_count as usize);
+        for _ in 0..entity_count {
+            let kind = r.str()?;
+            let pos = vec2::new(r.i16()? as f32, r.i16()? as f32);
+            // Width and height, nothing needs them yet.
+            r.take(4)?;
+            let prop_count = r.u8()?;
+            let mut props = Vec::with_capacity(prop_count as usize);
+            for _ in 0..prop_count {
+                let name = r.str()?;
+                let prop = match r.u8()? {
+                    0 => Prop::Int(r.i32()?),
+                    1 => Prop::Float(f32::from_bits(r.i32()? as u32)),
+                    2 => Prop::Bool(r.u8()? != 0),
+                    3 => Prop::Str(r.str()?),
+                    _ => return Err(LevelError::Invalid("bad property type")),
+                };
+                props.push((name, prop));
+            }
+            spawns.push(Spawn { kind, pos, props });
+        }
+
+        Ok(Self {
+            width,
+            height,
+            palette,
+            tiles,
+            layers,
+            cell_size,
+            cols,
+            rows,
+            cells,
+            spawns,
+            data,
+        })
+    }
+
+    pub fn palette(&self) -> Palette {
+        Palette {
+            data: self.data[self.palette.clone()].into(),
+        }
+    }
+
+    /// Sets up one `Text8bpp` background per layer of the level on `layers`, the topmost one on
+    /// `L0`, and uploads them.
+    ///
+    /// Uses the first 96KB of the engine's background VRAM, which `vram` must back, the palette
+    /// is up to the caller.
+    pub fn build_backgrounds<S: Screen>(
+        &self,
+        vram: &Vram,
+        layers: Layers<S, Text, Text, Text, Text>,
+    ) -> Result<Vec<Background>, VideoError> {
+        let Layers { l0, l1, l2, l3 } = layers;
+        let mut backgrounds = Vec::with_capacity(self.layers.len());
+        let slots = [l0, l1, l2, l3].into_iter();
+        for (i, (slot, (size, map))) in slots.zip(&self.layers).enumerate() {
+            let bg = slot.text8(vram, *size, i as i32 * MAP_BASE_STRIDE, TILE_BASE)?;
+            if i == 0 {
+                bg.set_tiles(&self.data[self.tiles.clone()]);
+            }
+            bg.set_map(&self.data[map.clone()]);
+            backgrounds.push(bg);
+        }
+        Ok(backgrounds)
+    }
+
+    /// Collision value of the cell at `pos`, 0 for empty cells and anywhere out of the level.
+    pub fn collision_at(&self, pos: vec2) -> u8 {
+        let (width, height) = self.cell_size;
+        if width == 0 || height == 0 || pos.x < 0.0 || pos.y < 0.0 {
+            return 0;
+        }
+        let col = pos.x as usize / width as usize;
+        let row = pos.y as usize / height as usize;
+        if col >= self.cols as usize || row >= self.rows as usize {
+            return 0;
+        }
+        self.data[self.cells.start + row * self.cols as usize + col]
+    }
+
+    /// Size of a collision cell in pixels.
+    pub fn cell_size(&self) -> vec2 {
+        vec2::new(self.cell_size.0 as f32, self.cell_size.1 as f32)
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Result<Range<usize>, LevelError> {
+        let range = self.pos..self.pos + len;
+        if range.end > self.data.len() {
+            return Err(LevelError::Invalid("truncated"));
+        }
+        self.pos = range.end;
+        Ok(range)
+    }
+
+    fn bytes<const N: usize>(&mut self) -> Result<[u8; N], LevelError> {
+        let range = self.take(N)?;
+        Ok(self.data[range].try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, LevelError> {
+        Ok(self.bytes::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, LevelError> {
+        self.bytes().map(u16::from_le_bytes)
+    }
+
+    fn i16(&mut self) -> Result<i16, LevelError> {
+        self.bytes().map(i16::from_le_bytes)
+    }
+
+    fn i32(&mut self) -> Result<i32, LevelError> {
+        self.bytes().map(i32::from_le_bytes)
+    }
+
+    fn str(&mut self) -> Result<String, LevelError> {
+        let len = self.u8()? as usize;
+        let range = self.take(len)?;
+        core::str::from_utf8(&self.data[range])
+            .map(String::from)
+            .map_err(|_| LevelError::Invalid("bad string"))
+    }
+}
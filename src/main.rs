@@ -1,27 +1,57 @@
-#![no_main]
-#![no_std]
+#![cfg_attr(not(feature = "host"), no_main)]
+#![cfg_attr(not(feature = "host"), no_std)]
+// On the DS tests are `#[test_case]`s, run by `libnds::testing` from `main`.
+#![cfg_attr(all(test, not(feature = "host")), feature(custom_test_frameworks))]
+#![cfg_attr(all(test, not(feature = "host")), test_runner(libnds::testing::run))]
+#![cfg_attr(
+    all(test, not(feature = "host")),
+    reexport_test_harness_main = "test_main"
+)]
+// Outside of tests the host build only simulates the player, see `sim`.
+#![cfg_attr(all(feature = "host", not(test)), allow(dead_code))]
 extern crate alloc;
-use alloc::boxed::Box;
+use alloc::{boxed::Box, vec::Vec};
 use core::error::Error;
-use core::ffi::*;
 // pub use glam::U8Vec2 as vec2;
 pub use glam::Vec2 as vec2;
+use level::Level;
 #[allow(unused_imports)]
 use libnds::sys::{arm9_bindings as nds, eprintln, println};
 use libnds::{
-    Gfx, Keys, OAM, SpriteColorFormat, SpriteConfig, SpriteEntry, SpriteMapping, SpriteSize,
-    background::{self as bg, BackgroundPtr},
-    fill_slice, fill_slice_u8, resources,
-    texture::{Palette, PaletteType, Texture},
-    video::{self, SCREEN_HEIGHT, SCREEN_WIDTH, VRamTypeA, VRamTypeB, VRamTypeC, VRamTypeD},
+    Attrs, Gfx, Keys, SpriteConfig, SpriteEntry, SpriteMapping, background as bg,
+    engine::{Engine, Main, Sub},
+    resources,
+    slots::SpriteSlot,
+    sound::{self, SoundBank},
+    texture::{PaletteType, SpriteAsset, Texture},
+    video::{self, SCREEN_HEIGHT, SCREEN_WIDTH},
+    vram::{Bank, Mapping, Region, Vram, VramError},
 };
+use prefab::{Behaviour, Physics, Spawner};
+
+#[cfg(all(test, not(feature = "host")))]
+mod device_tests;
+mod level;
+mod prefab;
+#[cfg(feature = "host")]
+mod sim;
+#[cfg(all(test, feature = "host"))]
+mod tests;
+
+#[allow(dead_code)]
+mod assets {
+    include!(concat!(env!("OUT_DIR"), "/assets.rs"));
+}
 
+#[cfg(not(feature = "host"))]
 #[unsafe(no_mangle)]
-extern "C" fn main() -> c_int {
+extern "C" fn main() -> core::ffi::c_int {
     resources::nitrofs_init();
     unsafe {
         nds::consoleDebugInit(nds::DebugDevice_NOCASH);
     }
+    #[cfg(test)]
+    test_main();
     match app() {
         Ok(()) => return 0,
         Err(e) => {
@@ -34,31 +64,58 @@ extern "C" fn main() -> c_int {
     }
 }
 
+/// On the host the game doesn't run, it [simulates](sim) the player.
+#[cfg(feature = "host")]
+fn main() -> std::process::ExitCode {
+    sim::main()
+}
+
 struct Sprite {
-    gfx: Gfx,
-    oam: OAM,
-    id: u8,
+    /// Before `gfx`, so the entry is hidden before its tiles are freed.
+    slot: SpriteSlot<Main>,
+    gfx: Gfx<Main>,
+    asset: &'static SpriteAsset,
+    frame: u16,
 }
 
 impl Sprite {
-    fn new(size: SpriteSize, format: SpriteColorFormat, oam: OAM, id: u8) -> Self {
-        let gfx = oam.allocate_gfx(size, format);
-        oam.set_sprite(id, &gfx, &SpriteConfig {
+    fn new(
+        texture: &Texture<&'static SpriteAsset>,
+        slot: SpriteSlot<Main>,
+    ) -> Result<Self, VramError> {
+        let gfx = slot
+            .oam()
+            .allocate_gfx(texture.meta.size, texture.meta.format)?;
+        slot.set(&gfx, &SpriteConfig {
+            palette_alpha: texture.meta.palette_bank.into(),
             ..Default::default()
         });
-        Self { gfx, oam, id }
+        let mut sprite = Self {
+            slot,
+            gfx,
+            asset: texture.meta,
+            frame: 0,
+        };
+        sprite.set_frame(texture, 0);
+        Ok(sprite)
     }
-    fn set_texture(&self, texture: &Texture) {
-        self.gfx.set_texture(&texture.img);
+    fn set_frame(&mut self, texture: &Texture<&'static SpriteAsset>, frame: u16) {
+        self.gfx.set_texture(texture.frame(frame));
+        self.frame = frame;
     }
-    fn set_pos(&self, x: u8, y: u8) {
-        self.oam.set_sprite_pos(self.id, x, y);
+    /// Bounds of the opaque pixels of the current frame, relative to the sprite's position.
+    fn bounds(&self) -> (vec2, vec2) {
+        let rect = self.asset.bounds(self.frame);
+        (
+            vec2::new(rect.x as _, rect.y as _),
+            vec2::new(rect.right() as _, rect.bottom() as _),
+        )
     }
-    fn entry(&mut self) -> &'static mut SpriteEntry {
-        &mut self.oam.sprites()[self.id as usize]
+    fn set_pos(&self, x: u8, y: u8) {
+        self.slot.set_pos(x, y);
     }
-    const fn size(&self) -> SpriteSize {
-        self.gfx.size()
+    fn entry(&mut self) -> &mut SpriteEntry {
+        self.slot.entry()
     }
 }
 
@@ -70,9 +127,9 @@ struct Player {
 const TICK: f32 = 1.0 / 60.0;
 
 impl Player {
-    fn new(sprite: Sprite) -> Self {
+    fn new(edata: EntityData) -> Self {
         Self {
-            edata: EntityData::new(sprite, Default::default()),
+            edata,
             airborne: true,
         }
     }
@@ -85,12 +142,19 @@ impl Entity for Player {
     fn update(
         &mut self,
         update_data @ UpdateData {
-            keys, just_pressed, ..
+            keys,
+            just_pressed,
+            level,
+            sounds,
+            ..
         }: &UpdateData,
     ) {
         let mut xvec = 0.0;
-        let maxvelx = 50.0;
-        let screen_end = vec2::new(SCREEN_WIDTH as f32 - 16.0, SCREEN_HEIGHT as f32 - 16.0);
+        let physics = self.edata.physics;
+        let (top_left, bottom_right) = self.edata.sprite.bounds();
+        // Where the squid stands, at the bottom middle of its opaque pixels.
+        let feet_offset = vec2::new((top_left.x + bottom_right.x) / 2.0, bottom_right.y);
+        let level_end = vec2::new(level.width as f32, level.height as f32) - bottom_right;
 
         if keys.contains(Keys::LEFT) {
             xvec = -1.0;
@@ -98,9 +162,9 @@ impl Entity for Player {
             xvec = 1.0;
         }
 
-        if self.edata.pos.y >= screen_end.y {
-            self.airborne = false;
-        }
+        let feet = self.edata.pos + feet_offset;
+        let on_solid = self.edata.vel.y >= 0.0 && level.collision_at(feet) != 0;
+        self.airborne = !(self.edata.pos.y >= level_end.y || on_solid);
 
         if self.airborne {
             if self.edata.vel.y < 0.0 && !keys.contains(Keys::A) {
@@ -110,33 +174,40 @@ impl Entity for Player {
             if just_pressed.contains(Keys::A) {
                 self.airborne = true;
                 eprintln!("a pressed");
-                self.edata.vel.y = -self.edata.acc.y / 2.0;
+                sounds.play(assets::sounds::Sound::Jump, 100, 64);
+                self.edata.vel.y = -physics.jump_speed;
             }
         }
 
-        let acc = 100.0;
         // Oposite movement should be faster
         if -self.edata.vel.x.signum() == xvec {
             self.edata.vel.x = 10.0 * xvec;
         }
 
-        let drag = 4.0;
-        self.edata.acc.y = 110.;
+        self.edata.acc.y = physics.gravity;
         self.edata.acc.x = if xvec != 0.0 {
-            xvec * acc
+            xvec * physics.acceleration
         } else {
             // Stopping motion
-            -self.edata.vel.x * drag
+            -self.edata.vel.x * physics.drag
         };
 
-        self.edata.vel = self.edata.vel.clamp_length_max(maxvelx);
+        self.edata.vel = self.edata.vel.clamp_length_max(physics.max_speed);
         self.edata.update(update_data);
         if self.edata.vel.x.abs() <= 0.05 {
             self.edata.vel.x = 0.0;
         }
         // eprintln!("{:?} {:?} {:?}", self.edata.vel, self.edata.acc, xvec);
 
-        self.edata.pos = self.edata.pos.clamp(vec2::new(0.0, 0.0), screen_end);
+        // Land on top of solid cells, but only when falling so they can be jumped through.
+        let feet = self.edata.pos + feet_offset;
+        if self.edata.vel.y > 0.0 && level.collision_at(feet) != 0 {
+            let cell_height = level.cell_size().y;
+            self.edata.pos.y = feet.y - feet.y % cell_height - feet_offset.y;
+            self.edata.vel.y = 0.0;
+        }
+
+        self.edata.pos = self.edata.pos.clamp(vec2::new(0.0, 0.0), level_end);
     }
 }
 
@@ -145,15 +216,17 @@ struct EntityData {
     pos: vec2,
     vel: vec2,
     acc: vec2,
+    physics: &'static Physics,
 }
 
 impl EntityData {
-    fn new(sprite: Sprite, pos: vec2) -> Self {
+    fn new(sprite: Sprite, pos: vec2, physics: &'static Physics) -> Self {
         Self {
             sprite,
             pos,
             vel: vec2::ZERO,
             acc: vec2::ZERO,
+            physics,
         }
     }
 }
@@ -170,10 +243,12 @@ impl Entity for EntityData {
 }
 
 #[derive(Clone, Copy)]
-struct UpdateData {
+struct UpdateData<'a> {
     keys: Keys,
     just_pressed: Keys,
     camera: f32,
+    level: &'a Level,
+    sounds: &'a SoundBank,
 }
 
 trait Entity {
@@ -194,108 +269,131 @@ fn update(entity: &mut dyn Entity, update_data: &UpdateData) {
     }
     entity.update(update_data);
     let data = entity.data_mut();
-    let start = data.pos;
-    let end = start
-        + vec2::new(
-            data.sprite.size().height() as _,
-            data.sprite.size().width() as _,
-        );
+    // Only the opaque pixels count, a sprite whose transparent corner is on screen is hidden.
+    let (top_left, bottom_right) = data.sprite.bounds();
+    let start = data.pos + top_left;
+    let end = data.pos + bottom_right;
     let hidden = end.cmple(vec2::ZERO).any()
         || start
             .cmpge(vec2::new(SCREEN_WIDTH as _, SCREEN_HEIGHT as _))
             .any();
-    data.sprite.entry().set_is_hidden(hidden);
+    // Only regular sprites can be hidden, the game has no affine ones yet.
+    if let Attrs::Regular(mut attrs) = data.sprite.entry().attrs() {
+        attrs.set_hidden(hidden);
+    }
 
     let [x, y] = data.pos.to_array().map(|val| val as u8);
     data.sprite.set_pos(x, y);
 }
 
+#[cfg(not(feature = "host"))]
 fn app() -> Result<(), Box<dyn Error>> {
-    let bg = Texture::load("nitro:/bg/bg.img.bin")?;
-    let bg_palette = Palette::load("nitro:/bg/pal.bin")?;
-
-    let squid = Texture::load("nitro:/Squid.img.bin")?;
-    let platform = Texture::load("nitro:/Platform.img.bin")?;
-    let sprite_palette = Palette::load("nitro:/pal.bin")?;
-    let oam_main = OAM::main();
-    let oam_sub = OAM::sub();
-    video::set_main(video::Mode2D::Mode5);
-    video::set_sub(video::Mode2D::Mode5);
-    video::set_primary_banks(
-        VRamTypeA::MainSprite0,
-        VRamTypeB::MainBg0,
-        VRamTypeC::SubBg,
-        VRamTypeD::SubSprite,
-    );
-    oam_main.init(SpriteMapping::SM1D128, false);
-    oam_sub.init(SpriteMapping::SM1D128, false);
-
-    bg_palette.write(&oam_main, PaletteType::Backgrounds);
-    bg_palette.write(&oam_sub, PaletteType::Backgrounds);
-    sprite_palette.write(&oam_main, PaletteType::Sprites);
-
-    let bg_gfx = oam_main.allocate_bg(
-        bg::Layer::L2,
-        bg::Type::Bmp8,
-        bg::Bitmap8Size::B8_256x256,
-        0,
-        0,
-    );
-    let bg_gfx_sub = oam_sub.allocate_bg(
-        bg::Layer::L2,
-        bg::Type::Bmp8,
-        bg::Bitmap8Size::B8_256x256,
-        0,
-        0,
-    );
-    bg_gfx.set_texture(&bg);
-    bg_gfx_sub.set_texture(&bg);
-
-    let player_sprite = Sprite::new(
-        SpriteSize::S16x16,
-        SpriteColorFormat::SP256Color,
-        oam_main,
-        0,
-    );
-    player_sprite.set_texture(&squid);
-
-    let plat_sprite = Sprite::new(
-        SpriteSize::S32x16,
-        SpriteColorFormat::SP256Color,
-        oam_main,
-        1,
-    );
-    plat_sprite.set_texture(&platform);
-    let mut platform = EntityData::new(plat_sprite, Default::default());
-
-    let mut player = Player::new(player_sprite);
-
-    let mut last_held = Keys::empty();
-    let mut camera = 0.0;
-    platform.vel.x = 100.0;
+    let mut game = Game::new()?;
     loop {
+        game.frame();
+    }
+}
+
+/// Everything the game loop works on.
+struct Game {
+    level: Level,
+    sounds: SoundBank,
+    entities: Vec<Box<dyn Entity>>,
+    /// Keeps the banks mapped as set up, nothing remaps them for now.
+    _vram: Vram,
+    main: Engine<Main>,
+    sub: Engine<Sub>,
+    last_held: Keys,
+    camera: f32,
+}
+
+impl Game {
+    /// Loads the assets, sets up both screens and spawns the level's entities.
+    fn new() -> Result<Self, Box<dyn Error>> {
+        resources::mount(resources::Pack::open(assets::PACK)?);
+        sound::enable();
+        let sounds = assets::sounds::BANK.load()?;
+        let level = assets::levels::LEVEL1.load()?;
+        let bg_palette = assets::bg::PALETTE.load()?;
+
+        let sprite_palette = assets::PALETTE.load()?;
+        let main = Engine::main();
+        let sub = Engine::sub();
+        let main_layers = main.configure(video::layout::Mode0);
+        let sub_layers = sub.configure(video::layout::Mode5);
+        let mut vram = Vram::take()?;
+        vram.map(Bank::A, Mapping::new(Region::MainSprite, 0))?;
+        vram.map(Bank::B, Mapping::new(Region::MainBg, 0))?;
+        vram.map(Bank::C, Mapping::new(Region::SubBg, 0))?;
+        vram.map(Bank::D, Mapping::new(Region::SubSprite, 0))?;
+        main.oam().init(&vram, SpriteMapping::SM1D128, false)?;
+        sub.oam().init(&vram, SpriteMapping::SM1D128, false)?;
+
+        level.palette().write(main, PaletteType::Backgrounds);
+        bg_palette.write(sub, PaletteType::Backgrounds);
+        sprite_palette.write(main, PaletteType::Sprites);
+
+        level.build_backgrounds(&vram, main_layers)?;
+        let bg = &assets::bg::BG;
+        let bg_gfx_sub = sub_layers.l2.init(&vram, bg.type_, bg.size, 0, 0)?;
+        bg.load_into(bg_gfx_sub)?;
+
+        // Every entry goes to the level's entities until there is a HUD to reserve some for.
+        let mut spawner = Spawner::new(main.oam().reserve_rest());
+        let mut entities: Vec<Box<dyn Entity>> = Vec::with_capacity(level.spawns.len());
+        for spawn in &level.spawns {
+            let Some(prefab) = prefab::find(&spawn.kind) else {
+                eprintln!("no prefab for entity kind `{}`, skipping it", spawn.kind);
+                continue;
+            };
+            let mut data = spawner.spawn(prefab, spawn.pos)?;
+            if let Some(vel_x) = spawn.float("vel_x") {
+                data.vel.x = vel_x;
+            }
+            let entity: Box<dyn Entity> = match prefab.behaviour {
+                Behaviour::Player => Box::new(Player::new(data)),
+                Behaviour::Move => Box::new(data),
+            };
+            entities.push(entity);
+        }
+
+        Ok(Self {
+            level,
+            sounds,
+            entities,
+            _vram: vram,
+            main,
+            sub,
+            last_held: Keys::empty(),
+            camera: 0.0,
+        })
+    }
+
+    /// Reads the keys, updates every entity and shows the result on the next vblank.
+    fn frame(&mut self) {
         let keys = libnds::held_keys();
-        let just_pressed = keys & !last_held;
-        last_held = keys;
+        let just_pressed = keys & !self.last_held;
+        self.last_held = keys;
         if keys.contains(Keys::UP) {
-            camera += 1.0;
+            self.camera += 1.0;
         } else if keys.contains(Keys::DOWN) {
-            camera -= 1.0;
+            self.camera -= 1.0;
         }
         let update_data = UpdateData {
             keys,
             just_pressed,
-            camera,
+            camera: self.camera,
+            level: &self.level,
+            sounds: &self.sounds,
         };
 
-        let entities: &mut [&mut dyn Entity] = &mut [&mut player, &mut platform];
-        for entity in entities {
-            update(*entity, &update_data);
+        for entity in &mut self.entities {
+            update(&mut **entity, &update_data);
         }
 
         libnds::wait_for_vblank();
         bg::update();
-        oam_sub.update();
-        oam_main.update();
+        self.sub.oam().update();
+        self.main.oam().update();
     }
 }
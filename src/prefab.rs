@@ -0,0 +1,90 @@
+//! Entity prefabs compiled by the build script from `data/prefabs`, see `build/prefab.rs`.
+
+use alloc::{boxed::Box, vec::Vec};
+use core::error::Error;
+
+use libnds::{
+    engine::Main,
+    slots::Pool,
+    texture::{SpriteAsset, Texture},
+};
+
+use crate::{EntityData, Sprite, assets, vec2};
+
+/// What drives an entity spawned from a prefab.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Behaviour {
+    /// Moves at the velocity it spawns with, if any.
+    Move,
+    /// Moved with the d-pad, jumps with A.
+    Player,
+}
+
+/// How an entity moves, in pixels and seconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Physics {
+    pub velocity: vec2,
+    pub gravity: f32,
+    pub acceleration: f32,
+    /// Fraction of its speed it loses per second when it stops moving.
+    pub drag: f32,
+    pub max_speed: f32,
+    pub jump_speed: f32,
+}
+
+/// An entity as described in `data/prefabs`.
+pub struct Prefab {
+    pub name: &'static str,
+    /// Which also gives its size and palette.
+    pub sprite: &'static SpriteAsset,
+    pub behaviour: Behaviour,
+    pub physics: Physics,
+}
+
+/// The prefab named `name`, e.g. a level's spawn kind.
+pub fn find(name: &str) -> Option<&'static Prefab> {
+    assets::prefabs::ALL
+        .iter()
+        .copied()
+        .find(|p| p.name == name)
+}
+
+/// Spawns entities from prefabs with sprites of `pool`, loading each sprite once.
+pub struct Spawner {
+    pool: Pool<Main>,
+    textures: Vec<Texture<&'static SpriteAsset>>,
+}
+
+impl Spawner {
+    pub fn new(pool: Pool<Main>) -> Self {
+        Self {
+            pool,
+            textures: Vec::new(),
+        }
+    }
+
+    /// A ready entity at `pos`, with its own sprite and the prefab's starting velocity.
+    ///
+    /// Fails if the sprite can't be loaded, or the pool or sprite VRAM is full.
+    pub fn spawn(
+        &mut self,
+        prefab: &'static Prefab,
+        pos: vec2,
+    ) -> Result<EntityData, Box<dyn Error>> {
+        let texture = match self
+            .textures
+            .iter()
+            .position(|t| core::ptr::eq(t.meta, prefab.sprite))
+        {
+            Some(i) => &self.textures[i],
+            None => {
+                self.textures.push(prefab.sprite.load()?);
+                self.textures.last().unwrap()
+            }
+        };
+        let sprite = Sprite::new(texture, self.pool.allocate()?)?;
+        let mut data = EntityData::new(sprite, pos, &prefab.physics);
+        data.vel = prefab.physics.velocity;
+        Ok(data)
+    }
+}
@@ -0,0 +1,194 @@
+//! Headless simulation of the player, for tuning its physics without an emulator.
+//!
+//! Built with the `host` feature the game runs this instead of itself: `just sim` spawns the
+//! player where `LEVEL1` puts it and updates it at the fixed [`TICK`] for as long as a script
+//! of held keys says. Each step of the script is `FRAMES` frames without keys, or
+//! `KEYS:FRAMES` with `+` separated [`Keys`] held, e.g. `120 right:60 a+right:20 120`.
+//! Arguments like `gravity=120` override the prefab's [`Physics`] for the run.
+//!
+//! The trace of every frame goes to stdout as CSV, and a summary of each jump and of how fast
+//! the player gets up to speed to stderr.
+
+use std::io::{self, BufWriter, Write};
+use std::process::ExitCode;
+
+use super::*;
+use libnds::sys::eprintln;
+
+#[derive(Clone, Copy)]
+struct Row {
+    keys: Keys,
+    pos: vec2,
+    vel: vec2,
+    airborne: bool,
+}
+
+pub fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let _ds = libnds::host::boot();
+    resources::nitrofs_init();
+    resources::mount(resources::Pack::open(assets::PACK)?);
+    let level = assets::levels::LEVEL1.load()?;
+    let sounds = assets::sounds::BANK.load()?;
+
+    let (spawn, prefab) = level
+        .spawns
+        .iter()
+        .find_map(|spawn| {
+            prefab::find(&spawn.kind)
+                .filter(|p| p.behaviour == Behaviour::Player)
+                .map(|p| (spawn, p))
+        })
+        .ok_or("the level has no player")?;
+    let mut physics = prefab.physics;
+    let mut script = Vec::new();
+    for arg in args.iter().flat_map(|arg| arg.split_whitespace()) {
+        match arg.split_once('=') {
+            Some((name, value)) => override_physics(&mut physics, name, value)?,
+            None => script.extend(parse_step(arg)?),
+        }
+    }
+
+    let mut vram = Vram::take()?;
+    vram.map(Bank::A, Mapping::new(Region::MainSprite, 0))?;
+    let oam = Engine::main().oam();
+    oam.init(&vram, SpriteMapping::SM1D128, false)?;
+    let mut data = Spawner::new(oam.reserve_rest()).spawn(prefab, spawn.pos)?;
+    data.physics = Box::leak(Box::new(physics));
+    let mut player = Player::new(data);
+
+    let mut rows = Vec::with_capacity(script.len());
+    let mut last_held = Keys::empty();
+    for keys in script {
+        let update_data = UpdateData {
+            keys,
+            just_pressed: keys & !last_held,
+            camera: 0.0,
+            level: &level,
+            sounds: &sounds,
+        };
+        last_held = keys;
+        update(&mut player, &update_data);
+        libnds::wait_for_vblank();
+        rows.push(Row {
+            keys,
+            pos: player.edata.pos,
+            vel: player.edata.vel,
+            airborne: player.airborne,
+        });
+    }
+
+    write_trace(&rows)?;
+    summarize(&rows, &physics);
+    Ok(())
+}
+
+fn override_physics(physics: &mut Physics, name: &str, value: &str) -> Result<(), Box<dyn Error>> {
+    let field = match name {
+        "gravity" => &mut physics.gravity,
+        "acceleration" => &mut physics.acceleration,
+        "drag" => &mut physics.drag,
+        "max_speed" => &mut physics.max_speed,
+        "jump_speed" => &mut physics.jump_speed,
+        _ => return Err(format!("`{name}` is not a physics property").into()),
+    };
+    *field = value
+        .parse()
+        .map_err(|e| format!("bad value for `{name}`: {e}"))?;
+    Ok(())
+}
+
+/// The keys held on each frame of a step of the script.
+fn parse_step(step: &str) -> Result<impl Iterator<Item = Keys>, Box<dyn Error>> {
+    let (names, frames) = step.rsplit_once(':').unwrap_or(("", step));
+    let frames: usize = frames
+        .parse()
+        .map_err(|e| format!("bad frame count in `{step}`: {e}"))?;
+    let mut keys = Keys::empty();
+    for name in names.split('+').filter(|name| !name.is_empty()) {
+        keys |= Keys::from_name(&name.to_ascii_uppercase())
+            .ok_or_else(|| format!("unknown key `{name}` in `{step}`"))?;
+    }
+    Ok(core::iter::repeat_n(keys, frames))
+}
+
+fn write_trace(rows: &[Row]) -> io::Result<()> {
+    let mut out = BufWriter::new(io::stdout().lock());
+    writeln!(out, "frame,time,keys,x,y,vel_x,vel_y,airborne")?;
+    for (frame, row) in rows.iter().enumerate() {
+        let keys: Vec<_> = row.keys.iter_names().map(|(name, _)| name).collect();
+        writeln!(
+            out,
+            "{frame},{:.4},{},{},{},{},{},{}",
+            frame as f32 * TICK,
+            keys.join("+"),
+            row.pos.x,
+            row.pos.y,
+            row.vel.x,
+            row.vel.y,
+            row.airborne
+        )?;
+    }
+    out.flush()
+}
+
+fn summarize(rows: &[Row], physics: &Physics) {
+    let seconds = |frames: usize| frames as f32 * TICK;
+
+    // Each stretch of frames in the air, and how high above where it left the ground it got.
+    let mut frame = 0;
+    while let Some(start) = (frame..rows.len()).find(|&i| rows[i].airborne) {
+        let end = (start..rows.len())
+            .find(|&i| !rows[i].airborne)
+            .unwrap_or(rows.len());
+        let ground = match start {
+            0 => rows[0].pos.y,
+            _ => rows[start - 1].pos.y,
+        };
+        let apex = rows[start..end]
+            .iter()
+            .map(|row| row.pos.y)
+            .fold(ground, f32::min);
+        let what = if apex < ground { "jump" } else { "fall" };
+        eprint!(
+            "{what} at frame {start}: {:.1} px high, {:.3} s in the air",
+            ground - apex,
+            seconds(end - start)
+        );
+        if end == rows.len() {
+            eprint!(" and still airborne");
+        }
+        eprintln!();
+        frame = end;
+    }
+
+    let walking = Keys::LEFT | Keys::RIGHT;
+    let top_speed = rows.iter().map(|row| row.vel.x.abs()).fold(0.0, f32::max);
+    eprintln!(
+        "top speed: {top_speed:.2} (max_speed {})",
+        physics.max_speed
+    );
+    let Some(start) = rows.iter().position(|row| row.keys.intersects(walking)) else {
+        return;
+    };
+    match rows[start..]
+        .iter()
+        .position(|row| row.vel.x.abs() >= physics.max_speed)
+    {
+        Some(frames) => eprintln!(
+            "max speed {:.3} s after walking from frame {start}",
+            seconds(frames + 1)
+        ),
+        None => eprintln!("never got up to max speed after walking from frame {start}"),
+    }
+}
@@ -0,0 +1,203 @@
+//! The game on the simulated DS of `libnds::host`, run with `just test-game`.
+
+use libnds::{Attrs, Keys, SpriteColorFormat, SpriteEntry, SpriteSize, host};
+
+use super::*;
+
+/// A game set up like `main` does, on a freshly booted DS.
+fn start() -> (host::Session, Game) {
+    let ds = host::boot();
+    resources::nitrofs_init();
+    (ds, Game::new().unwrap())
+}
+
+fn run(game: &mut Game, frames: usize) {
+    for _ in 0..frames {
+        game.frame();
+    }
+}
+
+fn player(game: &mut Game) -> &mut EntityData {
+    game.entities
+        .iter_mut()
+        .map(|entity| entity.data_mut())
+        .find(|data| core::ptr::eq(data.physics, &assets::prefabs::PLAYER.physics))
+        .expect("the level has no player")
+}
+
+#[test]
+fn loads_every_prefab_sprite() {
+    let _ds = host::boot();
+    resources::nitrofs_init();
+    resources::mount(resources::Pack::open(assets::PACK).unwrap());
+    for prefab in assets::prefabs::ALL {
+        let sprite = prefab.sprite;
+        let texture = sprite.load().unwrap();
+        assert_eq!(
+            texture.img.len(),
+            sprite.frames.len().max(1) * sprite.frame_len(),
+            "{}",
+            prefab.name
+        );
+        let masks = sprite.load_masks().unwrap();
+        let bounds = sprite.bounds(0);
+        let (x, y) = (bounds.x as i32, bounds.y as i32);
+        assert!(
+            (x..bounds.right() as i32).any(|x| masks.is_solid(0, x, y)),
+            "the top row of the bounds of {} has no solid pixel",
+            prefab.name
+        );
+    }
+}
+
+#[test]
+fn player_walks_and_stops() {
+    let (_ds, mut game) = start();
+    // Let the player land first.
+    run(&mut game, 120);
+    let start = player(&mut game).pos;
+
+    host::script_keys([Keys::RIGHT; 60]);
+    run(&mut game, 60);
+    let player = player(&mut game);
+    assert!(player.pos.x > start.x, "{} <= {}", player.pos.x, start.x);
+    assert!(player.vel.x > 0.0);
+
+    run(&mut game, 180);
+    assert_eq!(self::player(&mut game).vel.x, 0.0);
+}
+
+#[test]
+fn player_jumps_and_lands() {
+    let (_ds, mut game) = start();
+    run(&mut game, 120);
+    let ground = player(&mut game).pos.y;
+
+    host::script_keys([Keys::A; 20]);
+    let mut top = ground;
+    for _ in 0..120 {
+        game.frame();
+        top = top.min(player(&mut game).pos.y);
+    }
+    assert!(top < ground - 8.0, "jumped from {ground} up to {top} only");
+    assert_eq!(player(&mut game).pos.y, ground);
+}
+
+#[test]
+fn player_is_drawn() {
+    let (_ds, mut game) = start();
+    run(&mut game, 120);
+    let shown = host::render(game.main);
+
+    player(&mut game).sprite.slot.set_hidden(true);
+    game.main.oam().update();
+    assert_ne!(shown, host::render(game.main));
+}
+
+#[test]
+fn entities_have_their_own_sprite_until_dropped() {
+    let (_ds, mut game) = start();
+    let mut ids: Vec<u8> = game
+        .entities
+        .iter_mut()
+        .map(|entity| entity.data_mut().sprite.slot.id())
+        .collect();
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids.len(), game.entities.len(), "entities share entries");
+
+    game.entities.clear();
+    for id in ids {
+        let entry = &mut game.main.oam().sprites()[id as usize];
+        assert!(matches!(entry.attrs(), Attrs::Regular(attrs) if attrs.is_hidden()));
+    }
+}
+
+#[test]
+fn regular_sprite_attributes_round_trip() {
+    let mut entry = SpriteEntry::from_raw([0x00_20, 0x01_2C, 0x0005]);
+    let Attrs::Regular(mut attrs) = entry.attrs() else {
+        panic!("a sprite without bit 8 of attribute 0 is affine");
+    };
+    attrs.set_hidden(true);
+    attrs.set_h_flip(true);
+    attrs.set_v_flip(true);
+    assert_eq!(entry.raw(), [0x02_20, 0x31_2C, 0x0005]);
+
+    let mut entry = SpriteEntry::from_raw(entry.raw());
+    let Attrs::Regular(mut attrs) = entry.attrs() else {
+        panic!("hiding a sprite made it affine");
+    };
+    assert!(attrs.is_hidden() && attrs.h_flip() && attrs.v_flip());
+    attrs.set_hidden(false);
+    attrs.set_h_flip(false);
+    assert_eq!(entry.raw(), [0x00_20, 0x21_2C, 0x0005]);
+    assert_eq!((entry.y(), entry.x(), entry.tile_index()), (0x20, 0x12C, 5));
+}
+
+#[test]
+fn affine_sprite_attributes_round_trip() {
+    let mut entry = SpriteEntry::from_raw([0x00_20, 0x31_2C, 0x0005]);
+    let mut attrs = entry.make_affine(17, false);
+    assert_eq!((attrs.matrix(), attrs.double_size()), (17, false));
+    attrs.set_double_size(true);
+    assert_eq!(entry.raw(), [0x03_20, 0x23_2C, 0x0005]);
+
+    let mut entry = SpriteEntry::from_raw(entry.raw());
+    let Attrs::Affine(mut attrs) = entry.attrs() else {
+        panic!("a sprite with bit 8 of attribute 0 is regular");
+    };
+    assert_eq!((attrs.matrix(), attrs.double_size()), (17, true));
+    attrs.set_matrix(31);
+    assert_eq!(entry.raw(), [0x03_20, 0x3F_2C, 0x0005]);
+
+    let attrs = entry.make_regular();
+    assert!(!attrs.is_hidden() && !attrs.h_flip() && !attrs.v_flip());
+    assert_eq!(entry.raw(), [0x00_20, 0x01_2C, 0x0005]);
+}
+
+#[test]
+fn sprite_tiles_stay_in_backed_vram() {
+    let _ds = host::boot();
+    let oam = Engine::main().oam();
+    let mut vram = Vram::take().unwrap();
+    oam.init(&vram, SpriteMapping::SM1D128, false).unwrap();
+    let format = SpriteColorFormat::SP256Color;
+    assert_eq!(
+        oam.allocate_gfx(SpriteSize::S64x64, format).err(),
+        Some(VramError::Unbacked {
+            region: Region::MainSprite,
+            end: 4096,
+            space: 0
+        })
+    );
+
+    // Bank F backs 16KB, four 64x64 sprites of 256 colors.
+    vram.map(Bank::F, Mapping::new(Region::MainSprite, 0))
+        .unwrap();
+    oam.init(&vram, SpriteMapping::SM1D128, false).unwrap();
+    let gfx: Vec<_> = (0..4)
+        .map(|_| oam.allocate_gfx(SpriteSize::S64x64, format).unwrap())
+        .collect();
+    assert_eq!(gfx.last().unwrap().offset(), 3 * 4096);
+    assert!(oam.allocate_gfx(SpriteSize::S8x8, format).is_err());
+}
+
+#[test]
+fn backgrounds_stay_in_backed_vram() {
+    let _ds = host::boot();
+    let layers = Engine::sub().configure(video::layout::Mode0);
+    let mut vram = Vram::take().unwrap();
+    // Bank H backs the first 32KB, tile base 2 starts right after.
+    vram.map(Bank::H, Mapping::new(Region::SubBg, 0)).unwrap();
+    let size = bg::TextSize::T256x256;
+    assert!(layers.l0.text8(&vram, size, 0, 1).is_ok());
+    assert_eq!(
+        layers.l1.text8(&vram, size, 4, 2).err(),
+        Some(video::VideoError::Vram(VramError::Unbacked {
+            region: Region::SubBg,
+            end: 32 * 1024 + 64,
+            space: 32 * 1024
+        }))
+    );
+}
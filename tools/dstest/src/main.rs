@@ -0,0 +1,200 @@
+//! Runs a test ROM in an emulator and turns the report `libnds::testing` prints to the no$gba
+//! debug console into an exit code: 0 when every test passed, 1 when some didn't and 2 when they
+//! couldn't run.
+//!
+//! ```sh
+//! just test-ds
+//! dstest target/armv5te-nintendo-ds/debug/deps/dhgame-<hash>.nds
+//! ```
+//!
+//! Given a directory, it runs the newest ROM in it. The emulator is `melonDS`, or the command in
+//! `DS_EMULATOR`, which has to print the debug console to its stdout or stderr. A test that
+//! prints nothing for `DS_TEST_TIMEOUT` seconds (30 by default) counts as crashed, like one that
+//! panicked and stopped the run.
+
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode, Stdio};
+use std::sync::mpsc::{self, Sender};
+use std::time::Duration;
+use std::{env, fs, thread};
+
+/// What `libnds::testing::PREFIX` is.
+const PREFIX: &str = "dhtest:";
+
+/// A line of the report.
+enum Event<'a> {
+    Start(usize),
+    Run(&'a str),
+    Ok(&'a str),
+    Fail(&'a str, &'a str),
+    Done(usize, usize),
+}
+
+impl<'a> Event<'a> {
+    /// The report line in `line`, which the emulator may have put something in front of.
+    fn parse(line: &'a str) -> Option<Self> {
+        let report = line[line.find(PREFIX)? + PREFIX.len()..].trim();
+        let (kind, rest) = report.split_once(' ')?;
+        Some(match kind {
+            "start" => Self::Start(rest.parse().ok()?),
+            "run" => Self::Run(rest),
+            "ok" => Self::Ok(rest),
+            "fail" => {
+                let (name, error) = rest.split_once(": ").unwrap_or((rest, ""));
+                Self::Fail(name, error)
+            }
+            "done" => {
+                let (passed, failed) = rest.split_once(' ')?;
+                Self::Done(passed.parse().ok()?, failed.parse().ok()?)
+            }
+            _ => return None,
+        })
+    }
+}
+
+fn main() -> ExitCode {
+    let Some(path) = env::args().nth(1) else {
+        eprintln!("usage: dstest <rom.nds or directory>");
+        return ExitCode::from(2);
+    };
+    let rom = match find_rom(Path::new(&path)) {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("{path}: {e}");
+            return ExitCode::from(2);
+        }
+    };
+    let emulator = env::var("DS_EMULATOR").unwrap_or_else(|_| "melonDS".into());
+    let timeout = match env::var("DS_TEST_TIMEOUT") {
+        Ok(secs) => match secs.parse() {
+            Ok(secs) => Duration::from_secs(secs),
+            Err(e) => {
+                eprintln!("DS_TEST_TIMEOUT: {e}");
+                return ExitCode::from(2);
+            }
+        },
+        Err(_) => Duration::from_secs(30),
+    };
+
+    let mut words = emulator.split_whitespace();
+    let Some(program) = words.next() else {
+        eprintln!("DS_EMULATOR is empty");
+        return ExitCode::from(2);
+    };
+    let child = Command::new(program)
+        .args(words)
+        .arg(&rom)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            eprintln!("could not run {program}: {e}");
+            return ExitCode::from(2);
+        }
+    };
+    let (lines, received) = mpsc::channel();
+    forward_lines(child.stdout.take().unwrap(), lines.clone());
+    forward_lines(child.stderr.take().unwrap(), lines);
+
+    eprintln!("running {}", rom.display());
+    let mut started = false;
+    // Tests that started and aren't done.
+    let mut running = Vec::new();
+    let mut done = None;
+    loop {
+        // The emulator's stdout and stderr may not arrive in order, so once the report is done
+        // whatever is left on the other one gets a moment to arrive.
+        let wait = if done.is_some() {
+            Duration::from_millis(200)
+        } else {
+            timeout
+        };
+        // Timing out, or the emulator exiting.
+        let Ok(line) = received.recv_timeout(wait) else {
+            break;
+        };
+        let Some(event) = Event::parse(&line) else {
+            // The emulator's own output, or a panic message.
+            eprintln!("{line}");
+            continue;
+        };
+        match event {
+            Event::Start(count) => {
+                println!("running {count} tests");
+                started = true;
+            }
+            Event::Run(name) => running.push(name.to_owned()),
+            Event::Ok(name) => {
+                println!("test {name} ... ok");
+                running.retain(|running| running != name);
+            }
+            Event::Fail(name, error) => {
+                println!("test {name} ... FAILED: {error}");
+                running.retain(|running| running != name);
+            }
+            Event::Done(passed, failed) => done = Some((passed, failed)),
+        }
+    }
+    // Emulators keep running the ROM's idle loop, or may be stuck on a crash.
+    let _ = child.kill();
+    let _ = child.wait();
+
+    if let Some((passed, failed)) = done {
+        let result = if failed == 0 { "ok" } else { "FAILED" };
+        println!("\ntest result: {result}. {passed} passed; {failed} failed");
+        return if failed == 0 {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        };
+    }
+    match running.pop() {
+        Some(name) => println!("test {name} ... crashed, it panicked or hung"),
+        None if started => println!("the tests stopped before the end of the report"),
+        None => {
+            eprintln!(
+                "no test report from {}, does {program} print the debug console?",
+                rom.display()
+            );
+            return ExitCode::from(2);
+        }
+    }
+    ExitCode::FAILURE
+}
+
+/// `path` if it's a ROM, else the most recently built one in it.
+fn find_rom(path: &Path) -> std::io::Result<PathBuf> {
+    if !path.is_dir() {
+        return Ok(path.to_owned());
+    }
+    let mut newest = None;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        if entry.path().extension().is_none_or(|ext| ext != "nds") {
+            continue;
+        }
+        let modified = entry.metadata()?.modified()?;
+        if newest.as_ref().is_none_or(|(time, _)| modified > *time) {
+            newest = Some((modified, entry.path()));
+        }
+    }
+    newest
+        .map(|(_, path)| path)
+        .ok_or_else(|| std::io::Error::other("no .nds ROM in it"))
+}
+
+/// Sends every line read from `output` on `lines`, on a thread of its own.
+fn forward_lines(output: impl Read + Send + 'static, lines: Sender<String>) {
+    thread::spawn(move || {
+        for line in BufReader::new(output).lines() {
+            let Ok(line) = line else { break };
+            if lines.send(line).is_err() {
+                break;
+            }
+        }
+    });
+}
@@ -0,0 +1,53 @@
+//! Lists the assets in a pack written by the build script, checking it on the way.
+//!
+//! ```sh
+//! just pak romfs/assets.pak
+//! ```
+
+use std::process::ExitCode;
+
+#[allow(dead_code)]
+#[path = "../../../build/pack.rs"]
+mod pack;
+
+fn main() -> ExitCode {
+    let Some(path) = std::env::args().nth(1) else {
+        eprintln!("usage: pak <file.pak>");
+        return ExitCode::FAILURE;
+    };
+    let data = match std::fs::read(&path) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("{path}: could not open: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let entries = match pack::parse(&data) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("{path}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    println!("{:<10} {:>8} {:>8} {:<10} path", "id", "offset", "size", "crc");
+    for entry in &entries {
+        let compressed = data[entry.offset as usize..].starts_with(b"DHCZ");
+        println!(
+            "{:08x}   {:>8} {:>8} {:08x}   {}{}",
+            entry.id,
+            entry.offset,
+            entry.len,
+            entry.crc,
+            entry.path,
+            if compressed { " (compressed)" } else { "" }
+        );
+    }
+    let payload: u64 = entries.iter().map(|e| e.len as u64).sum();
+    println!(
+        "{} assets, {payload} bytes of payload in {} bytes, all OK",
+        entries.len(),
+        data.len()
+    );
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,131 @@
+//! Backgrounds, one pixel at a time.
+
+use crate::{Engine, bgcnt, byte, halfword, tile_index};
+
+/// How a background is drawn, which depends on the BG mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// Scrolled tiles, 4bpp or 8bpp, with 16 bit map entries.
+    Text,
+    /// Transformed 8bpp tiles with 8 bit map entries.
+    Affine,
+    /// Transformed 8bpp tiles with 16 bit map entries, or an 8bpp or direct color bitmap.
+    Extended,
+}
+
+impl Kind {
+    /// The kind of `layer` in BG mode `mode`, `None` if the mode has no such background.
+    pub fn of(mode: u32, layer: usize) -> Option<Self> {
+        let kind = match (mode, layer) {
+            (_, 0 | 1) | (0, _) | (1 | 3, 2) => Self::Text,
+            (1, 3) | (2, _) | (4, 2) => Self::Affine,
+            (3 | 4, 3) | (5, _) => Self::Extended,
+            _ => return None,
+        };
+        Some(kind)
+    }
+}
+
+/// Color of the background at `(x, y)` on the screen, `None` where it is transparent.
+pub fn pixel(engine: &Engine, layer: usize, kind: Kind, x: i32, y: i32) -> Option<u16> {
+    let cnt = engine.bgcnt[layer];
+    let size = cnt >> bgcnt::SIZE_SHIFT;
+    match kind {
+        Kind::Text => text(engine, layer, cnt, size, x, y),
+        Kind::Affine | Kind::Extended => {
+            let (width, height) = if kind == Kind::Extended && cnt & bgcnt::COLOR_256 != 0 {
+                [(128, 128), (256, 256), (512, 256), (512, 512)][size as usize]
+            } else {
+                (128 << size, 128 << size)
+            };
+            let (bx, by) = engine.affine[layer - 2].apply(x, y);
+            let (bx, by) = if cnt & bgcnt::WRAP != 0 {
+                (bx.rem_euclid(width), by.rem_euclid(height))
+            } else if (0..width).contains(&bx) && (0..height).contains(&by) {
+                (bx, by)
+            } else {
+                return None;
+            };
+            let (bx, by, width) = (bx as usize, by as usize, width as usize);
+            match kind {
+                Kind::Affine => affine(engine, cnt, bx, by, width),
+                _ if cnt & bgcnt::COLOR_256 == 0 => extended_tiles(engine, cnt, bx, by, width),
+                _ => bitmap(engine, cnt, bx, by, width),
+            }
+        }
+    }
+}
+
+fn text(engine: &Engine, layer: usize, cnt: u16, size: u16, x: i32, y: i32) -> Option<u16> {
+    let (width, height) = [(256, 256), (512, 256), (256, 512), (512, 512)][size as usize];
+    let (hofs, vofs) = engine.scroll[layer];
+    let bx = (x as usize + hofs as usize) % width;
+    let by = (y as usize + vofs as usize) % height;
+    let (tx, ty) = (bx / 8, by / 8);
+    // Maps are split into 32x32 tile blocks, left to right then top to bottom.
+    let block = match size {
+        1 => tx / 32,
+        2 => ty / 32,
+        3 => tx / 32 + ty / 32 * 2,
+        _ => 0,
+    };
+    let entry_addr = engine.screen_base(cnt) + block * 0x800 + (ty % 32 * 32 + tx % 32) * 2;
+    let entry = halfword(&engine.bg_vram, entry_addr);
+    let color_256 = cnt & bgcnt::COLOR_256 != 0;
+    let index = entry_pixel(engine, cnt, entry, color_256, bx, by)?;
+    let bank = if color_256 { 0 } else { entry >> 12 };
+    Some(engine.bg_palette[(bank * 16) as usize + index as usize])
+}
+
+fn affine(engine: &Engine, cnt: u16, bx: usize, by: usize, width: usize) -> Option<u16> {
+    let tile = byte(
+        &engine.bg_vram,
+        engine.screen_base(cnt) + by / 8 * (width / 8) + bx / 8,
+    );
+    let tile_addr = engine.char_base(cnt) + tile as usize * 64;
+    let index = tile_index(&engine.bg_vram, tile_addr, true, bx % 8, by % 8)?;
+    Some(engine.bg_palette[index as usize])
+}
+
+fn extended_tiles(engine: &Engine, cnt: u16, bx: usize, by: usize, width: usize) -> Option<u16> {
+    let entry_addr = engine.screen_base(cnt) + (by / 8 * (width / 8) + bx / 8) * 2;
+    let entry = halfword(&engine.bg_vram, entry_addr);
+    let index = entry_pixel(engine, cnt, entry, true, bx, by)?;
+    Some(engine.bg_palette[index as usize])
+}
+
+/// 8bpp or direct color, the lowest bit of the char base field telling which.
+fn bitmap(engine: &Engine, cnt: u16, bx: usize, by: usize, width: usize) -> Option<u16> {
+    // Bitmaps start at the screen base field in 16KB steps, whatever the engine's bases.
+    let base = (cnt >> bgcnt::SCREEN_BASE_SHIFT & 0x1F) as usize * 0x4000;
+    let at = by * width + bx;
+    if cnt >> bgcnt::CHAR_BASE_SHIFT & 1 != 0 {
+        let color = halfword(&engine.bg_vram, base + at * 2);
+        (color & 0x8000 != 0).then_some(color & 0x7FFF)
+    } else {
+        let index = byte(&engine.bg_vram, base + at);
+        (index != 0).then(|| engine.bg_palette[index as usize])
+    }
+}
+
+/// Palette index of the pixel at `(bx, by)` of the background, in the tile of a 16 bit `entry`.
+fn entry_pixel(
+    engine: &Engine,
+    cnt: u16,
+    entry: u16,
+    color_256: bool,
+    bx: usize,
+    by: usize,
+) -> Option<u8> {
+    let tile = (entry & 0x3FF) as usize;
+    let (mut px, mut py) = (bx % 8, by % 8);
+    if entry & 1 << 10 != 0 {
+        px = 7 - px;
+    }
+    if entry & 1 << 11 != 0 {
+        py = 7 - py;
+    }
+    let tile_len = if color_256 { 64 } else { 32 };
+    let tile_addr = engine.char_base(cnt) + tile * tile_len;
+    tile_index(&engine.bg_vram, tile_addr, color_256, px, py)
+}
@@ -0,0 +1,247 @@
+//! A software renderer of the DS 2D engines, so what the game draws can be checked on the host.
+//!
+//! An [`Engine`] holds the state an engine renders from, laid out as GBATEK describes it: its
+//! display control register, background registers, palettes, OAM, and the VRAM it sees at its
+//! background and OBJ addresses. Filling it in like libnds fills in the hardware and calling
+//! [`Engine::render`] gives the frame the screen would show.
+//!
+//! Backgrounds of every type of modes 0 to 5 are drawn, and sprites, affine ones included. Not
+//! emulated: 3D, the large bitmap of mode 6, windows, blending (semi-transparent sprites are drawn
+//! opaque), mosaic, bitmap sprites and extended palettes.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{vec, vec::Vec};
+
+mod bg;
+mod obj;
+
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 192;
+
+/// Bits of `DISPCNT`.
+pub mod dispcnt {
+    /// BG mode, 0 to 5 for what [`Engine`](crate::Engine) draws.
+    pub const BG_MODE_MASK: u32 = 0b111;
+    /// OBJ tiles are mapped one after the other rather than in a 32 tile wide matrix.
+    pub const OBJ_1D: u32 = 1 << 4;
+    pub const FORCED_BLANK: u32 = 1 << 7;
+    /// Enables BG0, shifted left by the layer for the others.
+    pub const BG0: u32 = 1 << 8;
+    pub const OBJ: u32 = 1 << 12;
+    /// 0 for a white screen, 1 for graphics.
+    pub const DISPLAY_MODE_SHIFT: u32 = 16;
+    /// OBJ tile numbers step by 32 bytes shifted left by this field in 1D mapping.
+    pub const OBJ_1D_BOUNDARY_SHIFT: u32 = 20;
+    /// Main engine only, in 64KB steps added to every text and affine background's bases.
+    pub const CHAR_BASE_SHIFT: u32 = 24;
+    pub const SCREEN_BASE_SHIFT: u32 = 27;
+}
+
+/// Bits of `BGxCNT`.
+pub mod bgcnt {
+    pub const PRIORITY_MASK: u16 = 0b11;
+    /// In 16KB steps.
+    pub const CHAR_BASE_SHIFT: u16 = 2;
+    /// 8bpp tiles, or a bitmap for extended backgrounds.
+    pub const COLOR_256: u16 = 1 << 7;
+    /// In 2KB steps, or 16KB for bitmaps.
+    pub const SCREEN_BASE_SHIFT: u16 = 8;
+    /// Affine backgrounds repeat instead of being transparent outside of their area.
+    pub const WRAP: u16 = 1 << 13;
+    pub const SIZE_SHIFT: u16 = 14;
+}
+
+/// The transform of an affine background, or of affine sprites.
+///
+/// `pa` to `pd` are 8.8 fixed point, `x` and `y` the 20.8 fixed point point of the background
+/// drawn at the top left of the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Affine {
+    pub pa: i16,
+    pub pb: i16,
+    pub pc: i16,
+    pub pd: i16,
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Affine {
+    pub const IDENTITY: Self = Self {
+        pa: 1 << 8,
+        pb: 0,
+        pc: 0,
+        pd: 1 << 8,
+        x: 0,
+        y: 0,
+    };
+
+    /// The point of the background drawn at `(x, y)` on the screen.
+    fn apply(&self, x: i32, y: i32) -> (i32, i32) {
+        (
+            (self.x + self.pa as i32 * x + self.pb as i32 * y) >> 8,
+            (self.y + self.pc as i32 * x + self.pd as i32 * y) >> 8,
+        )
+    }
+}
+
+/// The state of the main or sub 2D engine.
+pub struct Engine {
+    pub is_sub: bool,
+    pub dispcnt: u32,
+    pub bgcnt: [u16; 4],
+    /// `BGxHOFS` and `BGxVOFS`, of text backgrounds.
+    pub scroll: [(u16, u16); 4],
+    /// Of BG2 and BG3.
+    pub affine: [Affine; 2],
+    /// BGR555 colors, the first one being the backdrop.
+    pub bg_palette: [u16; 256],
+    pub obj_palette: [u16; 256],
+    /// 128 entries of 4 halfwords, the last halfword of every 4 entries making up a set of
+    /// affine parameters.
+    pub oam: [u16; 512],
+    /// As seen from 0x06000000 by the main engine, 0x06200000 by the sub one.
+    pub bg_vram: Vec<u8>,
+    /// As seen from 0x06400000 by the main engine, 0x06600000 by the sub one.
+    pub obj_vram: Vec<u8>,
+}
+
+impl Engine {
+    /// The main engine as it boots, showing a white screen.
+    pub fn main() -> Self {
+        Self::new(false, 512 * 1024, 256 * 1024)
+    }
+
+    /// The sub engine, whose VRAM is smaller and backgrounds have no engine wide bases.
+    pub fn sub() -> Self {
+        Self::new(true, 128 * 1024, 128 * 1024)
+    }
+
+    fn new(is_sub: bool, bg_vram: usize, obj_vram: usize) -> Self {
+        Self {
+            is_sub,
+            dispcnt: 0,
+            bgcnt: [0; 4],
+            scroll: [(0, 0); 4],
+            affine: [Affine::IDENTITY; 2],
+            bg_palette: [0; 256],
+            obj_palette: [0; 256],
+            oam: [0; 512],
+            bg_vram: vec![0; bg_vram],
+            obj_vram: vec![0; obj_vram],
+        }
+    }
+
+    /// The BG mode, which sets the kind of each background.
+    pub const fn bg_mode(&self) -> u32 {
+        self.dispcnt & dispcnt::BG_MODE_MASK
+    }
+
+    pub fn render(&self) -> Frame {
+        let display_mode = self.dispcnt >> dispcnt::DISPLAY_MODE_SHIFT & 0b11;
+        if display_mode == 0 || self.dispcnt & dispcnt::FORCED_BLANK != 0 {
+            return Frame {
+                pixels: vec![0x7FFF; SCREEN_WIDTH * SCREEN_HEIGHT],
+            };
+        }
+        let objs = if self.dispcnt & dispcnt::OBJ != 0 {
+            obj::render(self)
+        } else {
+            vec![None; SCREEN_WIDTH * SCREEN_HEIGHT]
+        };
+        let layers: Vec<_> = (0..4)
+            .filter(|&l| self.dispcnt & dispcnt::BG0 << l != 0)
+            .filter_map(|l| Some((l, bg::Kind::of(self.bg_mode(), l)?)))
+            .collect();
+
+        let mut pixels = Vec::with_capacity(SCREEN_WIDTH * SCREEN_HEIGHT);
+        for y in 0..SCREEN_HEIGHT as i32 {
+            for x in 0..SCREEN_WIDTH as i32 {
+                // Lowest priority first, sprites in front of backgrounds of the same priority
+                // and lower layers in front of higher ones.
+                let mut top = (self.bg_palette[0], (4, 0, 0));
+                if let Some((color, priority)) = objs[(y * SCREEN_WIDTH as i32 + x) as usize] {
+                    top = (color, (priority, 0, 0));
+                }
+                for &(l, kind) in &layers {
+                    let rank = (self.bgcnt[l] & bgcnt::PRIORITY_MASK, 1, l);
+                    if rank < top.1
+                        && let Some(color) = bg::pixel(self, l, kind, x, y)
+                    {
+                        top = (color, rank);
+                    }
+                }
+                pixels.push(top.0);
+            }
+        }
+        Frame { pixels }
+    }
+
+    /// Base of tiles of text and affine backgrounds, `char_base` being the field of `BGxCNT`.
+    fn char_base(&self, cnt: u16) -> usize {
+        let engine = self.engine_base(dispcnt::CHAR_BASE_SHIFT);
+        engine + (cnt >> bgcnt::CHAR_BASE_SHIFT & 0xF) as usize * 0x4000
+    }
+
+    /// Base of maps of text and affine backgrounds.
+    fn screen_base(&self, cnt: u16) -> usize {
+        let engine = self.engine_base(dispcnt::SCREEN_BASE_SHIFT);
+        engine + (cnt >> bgcnt::SCREEN_BASE_SHIFT & 0x1F) as usize * 0x800
+    }
+
+    fn engine_base(&self, shift: u32) -> usize {
+        if self.is_sub {
+            0
+        } else {
+            (self.dispcnt >> shift & 0b111) as usize * 0x10000
+        }
+    }
+}
+
+/// Reads a byte of VRAM, 0 past its end like unmapped VRAM.
+fn byte(vram: &[u8], at: usize) -> u8 {
+    vram.get(at).copied().unwrap_or(0)
+}
+
+fn halfword(vram: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([byte(vram, at), byte(vram, at + 1)])
+}
+
+/// Palette index of a pixel of a 4bpp or 8bpp tile, `None` for the transparent index 0.
+fn tile_index(vram: &[u8], tile_addr: usize, color_256: bool, x: usize, y: usize) -> Option<u8> {
+    let index = if color_256 {
+        byte(vram, tile_addr + y * 8 + x)
+    } else {
+        // The leftmost pixel is in the low nibble.
+        byte(vram, tile_addr + y * 4 + x / 2) >> (x % 2 * 4) & 0xF
+    };
+    (index != 0).then_some(index)
+}
+
+/// A rendered screen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// BGR555 colors, row by row.
+    pub pixels: Vec<u16>,
+}
+
+impl Frame {
+    pub fn pixel(&self, x: usize, y: usize) -> u16 {
+        self.pixels[y * SCREEN_WIDTH + x]
+    }
+
+    /// RGB8 pixels row by row, e.g. for a PNG.
+    pub fn to_rgb8(&self) -> Vec<u8> {
+        self.pixels.iter().flat_map(|&c| rgb8(c)).collect()
+    }
+}
+
+/// Expands a BGR555 color to RGB8, white staying white.
+pub fn rgb8(color: u16) -> [u8; 3] {
+    [0, 5, 10].map(|shift| {
+        let c = (color >> shift & 0x1F) as u8;
+        c << 3 | c >> 2
+    })
+}
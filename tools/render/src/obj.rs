@@ -0,0 +1,117 @@
+//! Sprites, drawn into a layer of their own before backgrounds are composited with it.
+
+use alloc::{vec, vec::Vec};
+
+use crate::{Engine, SCREEN_HEIGHT, SCREEN_WIDTH, dispcnt, tile_index};
+
+/// Width and height of sprites by shape (square, wide, tall) and size.
+const SIZES: [[(i32, i32); 4]; 3] = [
+    [(8, 8), (16, 16), (32, 32), (64, 64)],
+    [(16, 8), (32, 8), (32, 16), (64, 32)],
+    [(8, 16), (8, 32), (16, 32), (32, 64)],
+];
+const MODE_WINDOW: u16 = 2;
+const MODE_BITMAP: u16 = 3;
+
+/// Color and priority of the frontmost sprite at each pixel of the screen.
+pub fn render(engine: &Engine) -> Vec<Option<(u16, u16)>> {
+    let mut layer = vec![None; SCREEN_WIDTH * SCREEN_HEIGHT];
+    // Drawn from the last entry so that of sprites with the same priority, the first is on top.
+    for index in (0..128).rev() {
+        let [attr0, attr1, attr2] = [0, 1, 2].map(|a| engine.oam[index * 4 + a]);
+        let affine = attr0 & 1 << 8 != 0;
+        // Disables regular sprites, doubles the area of affine ones.
+        let bit9 = attr0 & 1 << 9 != 0;
+        let mode = attr0 >> 10 & 0b11;
+        let shape = (attr0 >> 14) as usize;
+        if (!affine && bit9) || mode == MODE_WINDOW || mode == MODE_BITMAP || shape == 3 {
+            continue;
+        }
+        let (width, height) = SIZES[shape][(attr1 >> 14) as usize];
+        let (area_width, area_height) = if affine && bit9 {
+            (width * 2, height * 2)
+        } else {
+            (width, height)
+        };
+        // Coordinates wrap around, so sprites can stick out of the top and left of the screen.
+        let mut y = (attr0 & 0xFF) as i32;
+        if y + area_height > 256 {
+            y -= 256;
+        }
+        let mut x = (attr1 & 0x1FF) as i32;
+        if x >= 256 {
+            x -= 512;
+        }
+        let params = affine.then(|| {
+            let group = (attr1 >> 9 & 0x1F) as usize * 16;
+            [3, 7, 11, 15].map(|at| engine.oam[group + at] as i16 as i32)
+        });
+        let sprite = Sprite {
+            tile: (attr2 & 0x3FF) as usize,
+            color_256: attr0 & 1 << 13 != 0,
+            bank: attr2 >> 12,
+            width,
+        };
+        let priority = attr2 >> 10 & 0b11;
+        let (hflip, vflip) = (attr1 & 1 << 12 != 0, attr1 & 1 << 13 != 0);
+
+        for sy in 0..area_height {
+            for sx in 0..area_width {
+                let (px, py) = (x + sx, y + sy);
+                if !(0..SCREEN_WIDTH as i32).contains(&px)
+                    || !(0..SCREEN_HEIGHT as i32).contains(&py)
+                {
+                    continue;
+                }
+                let (u, v) = match params {
+                    // Transformed around the center of the sprite's area.
+                    Some([pa, pb, pc, pd]) => {
+                        let (cx, cy) = (sx - area_width / 2, sy - area_height / 2);
+                        (
+                            ((pa * cx + pb * cy) >> 8) + width / 2,
+                            ((pc * cx + pd * cy) >> 8) + height / 2,
+                        )
+                    }
+                    None => (
+                        if hflip { width - 1 - sx } else { sx },
+                        if vflip { height - 1 - sy } else { sy },
+                    ),
+                };
+                if !(0..width).contains(&u) || !(0..height).contains(&v) {
+                    continue;
+                }
+                let at = py as usize * SCREEN_WIDTH + px as usize;
+                let in_front = layer[at].is_none_or(|(_, p)| priority <= p);
+                if in_front && let Some(color) = sprite.texel(engine, u as usize, v as usize) {
+                    layer[at] = Some((color, priority));
+                }
+            }
+        }
+    }
+    layer
+}
+
+struct Sprite {
+    tile: usize,
+    color_256: bool,
+    /// Palette bank of 4bpp sprites.
+    bank: u16,
+    width: i32,
+}
+
+impl Sprite {
+    fn texel(&self, engine: &Engine, u: usize, v: usize) -> Option<u16> {
+        let tile_len = if self.color_256 { 64 } else { 32 };
+        let (tx, ty) = (u / 8, v / 8);
+        let tile_addr = if engine.dispcnt & dispcnt::OBJ_1D != 0 {
+            let boundary = 32 << (engine.dispcnt >> dispcnt::OBJ_1D_BOUNDARY_SHIFT & 0b11);
+            self.tile * boundary + (ty * (self.width as usize / 8) + tx) * tile_len
+        } else {
+            // A matrix 32 tiles of 32 bytes wide, 8bpp tiles taking two.
+            self.tile * 32 + ty * 32 * 32 + tx * tile_len
+        };
+        let index = tile_index(&engine.obj_vram, tile_addr, self.color_256, u % 8, v % 8)?;
+        let bank = if self.color_256 { 0 } else { self.bank };
+        Some(engine.obj_palette[(bank * 16) as usize + index as usize])
+    }
+}
@@ -0,0 +1,344 @@
+//! Scenes rendered and compared against the PNGs in `tests/golden`.
+//!
+//! A scene that doesn't match has its frame written next to the test binaries, and the test
+//! prints where. `BLESS=1 just test-host` rewrites the golden images after an intended change.
+
+use std::{fs::File, io::BufWriter, path::Path};
+
+use render::{Affine, Engine, Frame, SCREEN_HEIGHT, SCREEN_WIDTH, bgcnt, dispcnt};
+
+fn check(name: &str, frame: &Frame) {
+    let golden = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{name}.png"));
+    let actual = frame.to_rgb8();
+    if std::env::var_os("BLESS").is_some() {
+        write_png(&golden, &actual);
+        return;
+    }
+    let expected = read_png(&golden)
+        .unwrap_or_else(|e| panic!("{}: {e}, run with BLESS=1 to create it", golden.display()));
+    if expected != actual {
+        let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("{name}.png"));
+        write_png(&path, &actual);
+        let differing = (expected.chunks(3).zip(actual.chunks(3)))
+            .filter(|(a, b)| a != b)
+            .count();
+        panic!(
+            "`{name}` differs from its golden image in {differing} pixels, it rendered {}",
+            path.display()
+        );
+    }
+}
+
+fn read_png(path: &Path) -> Result<Vec<u8>, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let mut reader = png::Decoder::new(file)
+        .read_info()
+        .map_err(|e| e.to_string())?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).map_err(|e| e.to_string())?;
+    if (info.width, info.height, info.color_type) != (256, 192, png::ColorType::Rgb) {
+        return Err("is not a 256x192 RGB8 image".into());
+    }
+    buf.truncate(info.buffer_size());
+    Ok(buf)
+}
+
+fn write_png(path: &Path, rgb: &[u8]) {
+    let file = File::create(path).unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(file),
+        SCREEN_WIDTH as u32,
+        SCREEN_HEIGHT as u32,
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(rgb).unwrap();
+}
+
+const fn rgb(r: u16, g: u16, b: u16) -> u16 {
+    r | g << 5 | b << 10
+}
+
+/// Graphics on with `mode` and the layers in `enabled`.
+const fn display(mode: u32, enabled: u32) -> u32 {
+    1 << dispcnt::DISPLAY_MODE_SHIFT | mode | enabled
+}
+
+fn put(vram: &mut [u8], at: usize, data: &[u8]) {
+    vram[at..at + data.len()].copy_from_slice(data);
+}
+
+fn put16(vram: &mut [u8], at: usize, value: u16) {
+    put(vram, at, &value.to_le_bytes());
+}
+
+fn tile4(index: impl Fn(usize, usize) -> u8) -> Vec<u8> {
+    (0..32)
+        .map(|i| {
+            let (x, y) = (i % 4 * 2, i / 4);
+            index(x, y) | index(x + 1, y) << 4
+        })
+        .collect()
+}
+
+fn tile8(index: impl Fn(usize, usize) -> u8) -> Vec<u8> {
+    (0..64).map(|i| index(i % 8, i / 8)).collect()
+}
+
+/// A triangle pointing up and left with a gradient, so flips show.
+fn triangle(x: usize, y: usize) -> u8 {
+    if x + y < 8 { (x + 1) as u8 } else { 0 }
+}
+
+fn set_obj(engine: &mut Engine, index: usize, attrs: [u16; 3]) {
+    engine.oam[index * 4..index * 4 + 3].copy_from_slice(&attrs);
+}
+
+#[test]
+fn text_backgrounds() {
+    let mut e = Engine::main();
+    e.dispcnt = display(0, dispcnt::BG0 | dispcnt::BG0 << 1);
+    e.bg_palette[0] = rgb(4, 4, 6);
+    for i in 1..16 {
+        e.bg_palette[i] = rgb(i as u16 * 2, 4, 4);
+        e.bg_palette[16 + i] = rgb(4, 4, i as u16 * 2);
+    }
+    e.bg_palette[32] = rgb(6, 20, 6);
+
+    // BG0: 4bpp, 512x256 in two map blocks, red on the left and blue on the right, scrolled so
+    // the seam is on screen. Tiles cycle through their flips.
+    e.bgcnt[0] =
+        1 << bgcnt::SIZE_SHIFT | 1 << bgcnt::CHAR_BASE_SHIFT | 2 << bgcnt::SCREEN_BASE_SHIFT;
+    e.scroll[0] = (128, 4);
+    put(&mut e.bg_vram, 0x4000 + 32, &tile4(triangle));
+    for ty in 0..32 {
+        for tx in 0..64 {
+            let flips = ((tx + ty) % 4) as u16;
+            let bank = (tx / 32) as u16;
+            let at = 0x1000 + tx / 32 * 0x800 + (ty * 32 + tx % 32) * 2;
+            put16(&mut e.bg_vram, at, 1 | flips << 10 | bank << 12);
+        }
+    }
+
+    // BG1: 8bpp, behind BG0, a checkerboard with holes showing the backdrop.
+    e.bgcnt[1] = 1 | bgcnt::COLOR_256 | 2 << bgcnt::CHAR_BASE_SHIFT | 4 << bgcnt::SCREEN_BASE_SHIFT;
+    put(
+        &mut e.bg_vram,
+        0x8000 + 64,
+        &tile8(|x, y| if (x / 4 + y / 4) % 2 == 0 { 32 } else { 0 }),
+    );
+    for i in 0..32 * 32 {
+        put16(&mut e.bg_vram, 0x2000 + i * 2, 1);
+    }
+    check("text_backgrounds", &e.render());
+}
+
+#[test]
+fn bitmap_backgrounds() {
+    let mut e = Engine::sub();
+    e.dispcnt = display(5, dispcnt::BG0 << 2 | dispcnt::BG0 << 3);
+    for i in 1..256 {
+        e.bg_palette[i] = rgb(0, (i / 8) as u16, (31 - i / 8) as u16);
+    }
+
+    // BG2: 8bpp 256x256 bitmap, behind BG3, with transparent diagonal stripes.
+    e.bgcnt[2] = 1 | bgcnt::COLOR_256 | 1 << bgcnt::SIZE_SHIFT;
+    for y in 0..256 {
+        for x in 0..256 {
+            let index = if (x + y) % 32 < 4 {
+                0
+            } else {
+                (x ^ y) as u8 | 1
+            };
+            e.bg_vram[y * 256 + x] = index;
+        }
+    }
+
+    // BG3: direct color 128x128 bitmap at 64KB, zoomed twice and moved right, with a
+    // transparent hole.
+    e.bgcnt[3] = bgcnt::COLOR_256 | 1 << bgcnt::CHAR_BASE_SHIFT | 4 << bgcnt::SCREEN_BASE_SHIFT;
+    e.affine[1] = Affine {
+        pa: 128,
+        pd: 128,
+        x: -64 << 8,
+        ..Affine::IDENTITY
+    };
+    for y in 0..128 {
+        for x in 0..128 {
+            let (dx, dy) = (x as i32 - 64, y as i32 - 64);
+            let color = if dx * dx + dy * dy < 24 * 24 {
+                0
+            } else {
+                0x8000 | rgb((x / 4) as u16, 31 - (y / 4) as u16, 8)
+            };
+            put16(&mut e.bg_vram, 0x10000 + (y * 128 + x) * 2, color);
+        }
+    }
+    check("bitmap_backgrounds", &e.render());
+}
+
+#[test]
+fn rotation_backgrounds() {
+    let mut e = Engine::main();
+    e.dispcnt = display(2, dispcnt::BG0 << 2 | dispcnt::BG0 << 3);
+    e.bg_palette[0] = rgb(0, 0, 8);
+    e.bg_palette[1] = rgb(31, 31, 0);
+    e.bg_palette[2] = rgb(31, 8, 0);
+    e.bg_palette[3] = rgb(20, 20, 20);
+
+    // BG2: 256x256, rotated by about 30 degrees around the top left and wrapping.
+    e.bgcnt[2] = 1 | bgcnt::WRAP | 1 << bgcnt::SIZE_SHIFT | 1 << bgcnt::CHAR_BASE_SHIFT;
+    put(
+        &mut e.bg_vram,
+        0x4000 + 64,
+        &tile8(|x, y| if x < 4 { 1 } else { (y < 4) as u8 * 2 }),
+    );
+    for i in 0..32 * 32 {
+        e.bg_vram[i] = (i / 32 + i % 32) as u8 % 2;
+    }
+    e.affine[0] = Affine {
+        pa: 222,
+        pb: -128,
+        pc: 128,
+        pd: 222,
+        ..Affine::IDENTITY
+    };
+
+    // BG3: 128x128, in front, shrunk by half and not wrapping, so it covers a 64x64 square.
+    e.bgcnt[3] = 1 << bgcnt::CHAR_BASE_SHIFT | 2 << bgcnt::SCREEN_BASE_SHIFT;
+    put(
+        &mut e.bg_vram,
+        0x4000 + 128,
+        &tile8(|x, y| if (x + y) % 4 == 0 { 3 } else { 0 }),
+    );
+    for i in 0..16 * 16 {
+        e.bg_vram[0x1000 + i] = 2;
+    }
+    e.affine[1] = Affine {
+        pa: 512,
+        pd: 512,
+        x: -96 << 9,
+        y: -64 << 9,
+        ..Affine::IDENTITY
+    };
+    check("rotation_backgrounds", &e.render());
+}
+
+#[test]
+fn sprites() {
+    let mut e = Engine::main();
+    // 1D mapping with tile numbers in 128 byte steps, like `SpriteMapping::SM1D128`.
+    e.dispcnt = display(0, dispcnt::BG0 | dispcnt::OBJ)
+        | dispcnt::OBJ_1D
+        | 2 << dispcnt::OBJ_1D_BOUNDARY_SHIFT;
+    e.bg_palette[0] = rgb(10, 12, 14);
+    e.bg_palette[1] = rgb(6, 6, 6);
+    for i in 1..16 {
+        e.obj_palette[16 + i] = rgb(31, i as u16 * 2, 0);
+        e.obj_palette[32 + i] = rgb(0, i as u16 * 2, 31);
+    }
+    for i in 48..256 {
+        e.obj_palette[i] = rgb(0, (i % 32) as u16, (i / 8) as u16);
+    }
+
+    // BG0: a band across the screen at priority 1.
+    e.bgcnt[0] = 1 | 1 << bgcnt::CHAR_BASE_SHIFT;
+    put(&mut e.bg_vram, 0x4000 + 32, &tile4(|_, _| 1));
+    for ty in 8..12 {
+        for tx in 0..32 {
+            put16(&mut e.bg_vram, (ty * 32 + tx) * 2, 1);
+        }
+    }
+
+    // Tile 1: a 16x16 4bpp arrow made of four triangles, 128 bytes in.
+    let quarters = [
+        tile4(triangle),
+        tile4(|x, y| triangle(7 - x, y)),
+        tile4(|x, y| triangle(x, 7 - y)),
+        tile4(|x, y| (x == y) as u8 * 15),
+    ];
+    for (i, quarter) in quarters.iter().enumerate() {
+        put(&mut e.obj_vram, 128 + i * 32, quarter);
+    }
+    // Tile 2: a 32x16 8bpp gradient with a transparent border.
+    let tiles: Vec<u8> = (0..8)
+        .flat_map(|t| {
+            tile8(move |x, y| {
+                let (sx, sy) = (t % 4 * 8 + x, t / 4 * 8 + y);
+                let border = sx == 0 || sy == 0 || sx == 31 || sy == 15;
+                if border { 0 } else { (48 + sx * 6) as u8 }
+            })
+        })
+        .collect();
+    put(&mut e.obj_vram, 256, &tiles);
+
+    let square16 = 1 << 14;
+    let arrow = |bank: u16, priority: u16| 1 | priority << 10 | bank << 12;
+    // Regular sprites and their flips.
+    set_obj(&mut e, 0, [20, 20 | square16, arrow(1, 0)]);
+    set_obj(&mut e, 1, [20, 40 | 1 << 12 | square16, arrow(1, 0)]);
+    set_obj(&mut e, 2, [20, 60 | 1 << 13 | square16, arrow(1, 0)]);
+    set_obj(&mut e, 3, [20, 80 | 3 << 12 | square16, arrow(1, 0)]);
+    // A wide 8bpp sprite crossing the band, behind it at priority 2.
+    set_obj(
+        &mut e,
+        4,
+        [56 | 1 << 13 | 1 << 14, 20 | 2 << 14, 2 | 2 << 10],
+    );
+    // The same at priority 0, in front of it.
+    set_obj(&mut e, 5, [56 | 1 << 13 | 1 << 14, 60 | 2 << 14, 2]);
+    // Wrapping around the left and top edges, and cut by the bottom one.
+    set_obj(&mut e, 6, [100, 500 | square16, arrow(2, 0)]);
+    set_obj(&mut e, 7, [250, 120 | square16, arrow(2, 0)]);
+    set_obj(&mut e, 8, [184, 140 | square16, arrow(2, 0)]);
+    // Disabled.
+    set_obj(&mut e, 9, [100 | 1 << 9, 100 | square16, arrow(1, 0)]);
+    // Overlapping at the same priority, the first entry on top.
+    set_obj(&mut e, 10, [120, 160 | square16, arrow(2, 0)]);
+    set_obj(&mut e, 11, [124, 164 | square16, arrow(1, 0)]);
+    // Affine, rotated by 45 degrees and scaled up by 1.5 in a double size area, with the
+    // parameters of group 1.
+    set_obj(
+        &mut e,
+        12,
+        [20 | 3 << 8, 120 | 1 << 9 | square16, arrow(1, 0)],
+    );
+    for (i, param) in [121i16, -121, 121, 121].into_iter().enumerate() {
+        e.oam[16 + i * 4 + 3] = param as u16;
+    }
+    check("sprites", &e.render());
+}
+
+#[test]
+fn sprites_2d_mapping() {
+    let mut e = Engine::sub();
+    e.dispcnt = display(0, dispcnt::OBJ);
+    e.bg_palette[0] = rgb(2, 2, 2);
+    for i in 1..256 {
+        e.obj_palette[i] = rgb((i % 32) as u16, 31 - (i % 32) as u16, (i / 8) as u16);
+    }
+    // A 16x16 8bpp sprite at tile 4 of the 32 tile wide matrix: two 32 byte units per tile,
+    // rows of tiles 1KB apart.
+    for (t, (tx, ty)) in [(0, 0), (1, 0), (0, 1), (1, 1)].into_iter().enumerate() {
+        let tile = tile8(|x, y| (1 + t * 64 + y * 8 + x) as u8);
+        put(&mut e.obj_vram, 4 * 32 + ty * 1024 + tx * 64, &tile);
+    }
+    set_obj(&mut e, 0, [40 | 1 << 13, 40 | 1 << 14, 4]);
+    set_obj(&mut e, 1, [40 | 1 << 13 | 3 << 8, 80 | 1 << 14, 4]);
+    // Scaled up four times around the center of a double size area.
+    for (i, param) in [64i16, 0, 0, 64].into_iter().enumerate() {
+        e.oam[i * 4 + 3] = param as u16;
+    }
+    check("sprites_2d_mapping", &e.render());
+}
+
+#[test]
+fn blank_screens() {
+    let mut e = Engine::main();
+    check("blank", &e.render());
+    e.dispcnt = display(0, dispcnt::BG0) | dispcnt::FORCED_BLANK;
+    assert_eq!(e.render(), Engine::main().render());
+}